@@ -0,0 +1,4 @@
+// The dash hash table is still being built and is not wired into the store yet
+#[allow(dead_code)]
+mod dash;
+pub mod reactive_store;
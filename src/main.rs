@@ -1,35 +1,42 @@
-#![feature(reentrant_lock)]
-
-use crate::reactive_store::{ReactiveStore, StoreValue};
+use r_reactive::reactive_store::{
+    ChangeEvent, OverflowPolicy, ReactiveStore, StoreConfig, StoreValue,
+};
+use std::collections::HashSet;
 use std::time::Duration;
 
-mod dash;
-mod reactive_store;
-
 #[tokio::main]
 async fn main() {
-    let store = ReactiveStore::new();
+    // An unbounded subscriber is needed to observe every one of the 100000 expiries
+    let store = ReactiveStore::with_config(StoreConfig {
+        overflow: OverflowPolicy::Unbounded,
+        ..StoreConfig::default()
+    });
     let mut sub = store.subscribe();
 
     // Insert 100000 items with same ttl and see if the reactive store can handle it
-
-    for i in 0..100000 {
-        store.set_with_ttl(
-            &format!("key{}", i),
-            StoreValue::Text(format!("value{}", i)),
+    store
+        .set_many_with_ttl(
+            (0..100000).map(|i| (format!("key{}", i), StoreValue::Text(format!("value{}", i)))),
             Duration::from_secs(1),
-        );
-    }
+        )
+        .unwrap();
     // Wait for the items to expire
     tokio::time::sleep(Duration::from_secs(2)).await;
     // Check if the items are expired
-    for i in 0..100000 {
-        assert_eq!(store.get(&format!("key{}", i)), None);
-    }
-    // Check if the subscriber received the expired messages
-    for i in 0..100000 {
-        let (k, v) = sub.recv().await.unwrap();
-        assert_eq!(k, format!("key{}", i));
-        assert_eq!(v, StoreValue::Text("EXPIRED".into()));
+    let keys: Vec<String> = (0..100000).map(|i| format!("key{}", i)).collect();
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    assert!(store.get_many(&keys).iter().all(Option::is_none));
+    // The whole insert arrives as a single batch
+    let event = sub.recv().await.unwrap();
+    assert!(matches!(event, ChangeEvent::Committed { changes, .. } if changes.len() == 100000));
+    // Check if the subscriber received the expired messages, in no particular order
+    let mut expired = HashSet::new();
+    for _ in 0..100000 {
+        let event = sub.recv().await.unwrap();
+        let ChangeEvent::Expired { key, .. } = event else {
+            panic!("expected an expiry, got {event:?}");
+        };
+        expired.insert(key);
     }
+    assert_eq!(expired.len(), 100000);
 }
@@ -0,0 +1,146 @@
+use crate::reactive_store::{ReactiveStore, StoreData, StoreError, StoreKey, Transaction};
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/**
+ * Bulk reads and writes that take the lock once. Writes are applied all
+ * together and published as a single
+ * [`ChangeEvent::Committed`](crate::reactive_store::ChangeEvent::Committed)
+ * event; when a key is given more than once, the last value wins.
+ */
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    pub fn set_many<I>(&self, entries: I) -> Result<(), StoreError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.insert_many(entries, None)?;
+        Ok(())
+    }
+
+    /// Like [`ReactiveStore::set_many`], with every key expiring after `ttl`.
+    pub fn set_many_with_ttl<I>(&self, entries: I, ttl: Duration) -> Result<(), StoreError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let deadline = Instant::now() + ttl;
+        let keys = self.insert_many(entries, Some(deadline))?;
+        self.expiry.schedule_all(keys, deadline);
+        self.ensure_reaper();
+        Ok(())
+    }
+
+    /// Returns the values of `keys` in the same order, `None` for missing keys.
+    pub fn get_many<Q>(&self, keys: &[&Q]) -> Vec<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        let data = self.data.read().unwrap();
+        keys.iter()
+            .map(|key| {
+                data.get(*key)
+                    .filter(|entry| !entry.is_expired(now))
+                    .map(|entry| entry.value.clone())
+            })
+            .collect()
+    }
+
+    /// Removes every one of `keys`, returning how many existed.
+    pub fn remove_many<Q>(&self, keys: &[&Q]) -> Result<usize, StoreError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.writing(|data| {
+            self.writable()?;
+            let mut tx = Transaction::new(data);
+            for key in keys {
+                tx.stage((*key).to_owned(), None);
+            }
+            let writes = tx.into_writes();
+            let removed = writes.len();
+            self.commit_locked(data, writes, None)?;
+            Ok(removed)
+        })
+    }
+
+    /// Writes `entries` under one lock, returning the keys written.
+    fn insert_many<I>(&self, entries: I, expires_at: Option<Instant>) -> Result<Vec<K>, StoreError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.writing(|data| {
+            self.writable()?;
+            let mut tx = Transaction::new(data);
+            for (key, value) in entries {
+                tx.stage(key, Some(value));
+            }
+            let writes = tx.into_writes();
+            let keys = writes.iter().map(|(key, _)| key.clone()).collect();
+            self.commit_locked(data, writes, expires_at)?;
+            Ok(keys)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::{ChangeEvent, StoreValue, TryRecvError};
+
+    #[test]
+    fn test_batch_operations() {
+        let store = ReactiveStore::new();
+        store.set("a", StoreValue::Counter(0)).unwrap();
+        let mut events = store.subscribe();
+
+        store
+            .set_many([
+                ("a".to_string(), StoreValue::Counter(1)),
+                ("b".to_string(), StoreValue::Counter(2)),
+                ("a".to_string(), StoreValue::Counter(3)),
+            ])
+            .unwrap();
+        assert_eq!(
+            store.get_many(&["a", "b", "c"]),
+            [
+                Some(StoreValue::Counter(3)),
+                Some(StoreValue::Counter(2)),
+                None
+            ]
+        );
+        let Ok(ChangeEvent::Committed { changes, .. }) = events.try_recv() else {
+            panic!("expected one committed batch");
+        };
+        assert_eq!(changes.len(), 2);
+
+        assert_eq!(store.remove_many(&["a", "c"]), Ok(1));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ChangeEvent::Committed { changes, .. }) if changes.len() == 1
+        ));
+        assert_eq!(store.remove_many(&["a"]), Ok(0));
+        assert_eq!(events.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[tokio::test]
+    async fn test_set_many_with_ttl_expires() {
+        let store = ReactiveStore::new();
+        store
+            .set_many_with_ttl(
+                (0..100).map(|i| (format!("key{i}"), StoreValue::Counter(i))),
+                Duration::from_millis(50),
+            )
+            .unwrap();
+        assert_eq!(store.get("key99"), Some(StoreValue::Counter(99)));
+        let mut events = store.subscribe();
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(store.get("key0").is_none());
+        for _ in 0..100 {
+            assert!(matches!(events.try_recv(), Ok(ChangeEvent::Expired { .. })));
+        }
+    }
+}
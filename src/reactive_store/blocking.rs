@@ -0,0 +1,392 @@
+use crate::reactive_store::{
+    ChangeEvent, Edit, End, Entry, ListEdit, ReactiveStore, StoreData, StoreError, StoreKey,
+    StoreValue,
+};
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/**
+ * Callers of [`ReactiveStore::blocking_pop_front`] waiting for an element.
+ * A waiter is queued under every key it waits on and is woken by the first
+ * of them to receive an element; the entries left in the other queues are
+ * skipped once its sender is gone. A woken waiter pops the element itself,
+ * so an element only leaves its list once a waiter has taken it.
+ */
+#[derive(Debug, Default)]
+pub(crate) struct BlockedPops {
+    next_id: u64,
+    queues: HashMap<String, VecDeque<u64>>,
+    senders: HashMap<u64, oneshot::Sender<String>>,
+    /// Waiters woken for each key that have not popped yet.
+    woken: HashMap<String, usize>,
+}
+
+impl BlockedPops {
+    /// Queues a waiter on `keys`, ahead of the others if it `retries`.
+    fn register(&mut self, keys: &[&str], retries: bool) -> (u64, oneshot::Receiver<String>) {
+        let (tx, rx) = oneshot::channel();
+        self.next_id += 1;
+        let id = self.next_id;
+        for key in keys {
+            let queue = self.queues.entry(key.to_string()).or_default();
+            if retries {
+                queue.push_front(id);
+            } else {
+                queue.push_back(id);
+            }
+        }
+        self.senders.insert(id, tx);
+        (id, rx)
+    }
+
+    fn unregister(&mut self, id: u64, keys: &[String]) {
+        self.senders.remove(&id);
+        for key in keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|waiting| *waiting != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Takes the longest waiting live sender queued under `key`.
+    fn next_waiter(&mut self, key: &str) -> Option<oneshot::Sender<String>> {
+        let queue = self.queues.get_mut(key)?;
+        let mut found = None;
+        while let Some(id) = queue.pop_front() {
+            match self.senders.remove(&id) {
+                Some(sender) if !sender.is_closed() => {
+                    found = Some(sender);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        if queue.is_empty() {
+            self.queues.remove(key);
+        }
+        found
+    }
+
+    /// Called by a waiter woken for `key` once it holds the data lock.
+    fn woke(&mut self, key: &str) {
+        if let Some(woken) = self.woken.get_mut(key) {
+            *woken -= 1;
+            if *woken == 0 {
+                self.woken.remove(key);
+            }
+        }
+    }
+}
+
+/**
+ * Unregisters a waiter that gave up. If it was woken meanwhile, the element
+ * it was woken for is still in its list, and goes to the next waiter.
+ */
+struct WaitGuard {
+    store: ReactiveStore,
+    id: u64,
+    keys: Vec<String>,
+    rx: oneshot::Receiver<String>,
+}
+
+impl WaitGuard {
+    fn take_wake(&mut self) -> Option<String> {
+        self.rx.close();
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        self.store
+            .blocked
+            .lock()
+            .unwrap()
+            .unregister(self.id, &self.keys);
+        if let Some(key) = self.take_wake() {
+            let mut data = self.store.data.write().unwrap();
+            self.store.blocked.lock().unwrap().woke(&key);
+            self.store.serve_blocked(&mut data, key.as_str());
+        }
+    }
+}
+
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    /**
+     * Wakes as many callers blocked on `key` as the list there has elements
+     * for, oldest first. Called with the data lock held after every write
+     * that can add elements to a list. Only values with a
+     * [`StoreData::list_len`] have elements to wait for.
+     */
+    pub(crate) fn serve_blocked<Q>(&self, data: &mut HashMap<K, Entry<V>>, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut blocked = self.blocked.lock().unwrap();
+        if blocked.queues.is_empty() {
+            return;
+        }
+        let Some((name, entry)) = data.get_key_value(key) else {
+            return;
+        };
+        let (Some(name), Some(len)) = (name.as_text(), entry.value.list_len()) else {
+            return;
+        };
+        while blocked.woken.get(name).copied().unwrap_or(0) < len {
+            let Some(sender) = blocked.next_waiter(name) else {
+                return;
+            };
+            if sender.send(name.to_string()).is_ok() {
+                *blocked.woken.entry(name.to_string()).or_default() += 1;
+            }
+        }
+    }
+}
+
+impl ReactiveStore {
+    /**
+     * Pops the first element of the first non-empty list among `keys`,
+     * waiting until one of them receives an element if they are all empty.
+     * Returns the key and the element, or `None` once `timeout` has passed.
+     * `None` as the timeout waits indefinitely.
+     *
+     * Waiters are woken in the order they started waiting, one for each
+     * element pushed, and each element goes to exactly one of them. A woken
+     * waiter pops the element itself, which is published like any other
+     * pop; one that finds the element taken by a non-blocking pop waits
+     * again, ahead of the others. Dropping the returned future never loses
+     * an element.
+     */
+    pub async fn blocking_pop_front(
+        &self,
+        keys: &[&str],
+        timeout: Option<Duration>,
+    ) -> Result<Option<(String, StoreValue)>, StoreError> {
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        let mut woken: Option<String> = None;
+        loop {
+            let now = Instant::now();
+            let attempt = self.writing(|data| -> Result<_, StoreError> {
+                if let Some(key) = &woken {
+                    self.blocked.lock().unwrap().woke(key);
+                }
+                let popped = self
+                    .writable()
+                    .and_then(|()| self.pop_first_locked(data, keys, now));
+                let retries = woken.is_some();
+                if let Some(key) = woken.take() {
+                    // Whatever this waiter did not take goes to the next one
+                    self.serve_blocked(data, key.as_str());
+                }
+                if let Some(popped) = popped? {
+                    return Ok(ControlFlow::Break(popped));
+                }
+                // Registering before the data lock is released means no push can be missed
+                let registered = self.blocked.lock().unwrap().register(keys, retries);
+                Ok(ControlFlow::Continue(registered))
+            })?;
+            let (id, rx) = match attempt {
+                ControlFlow::Break(popped) => return Ok(Some(popped)),
+                ControlFlow::Continue(registered) => registered,
+            };
+
+            let mut guard = WaitGuard {
+                store: self.clone(),
+                id,
+                keys: keys.iter().map(|key| key.to_string()).collect(),
+                rx,
+            };
+            let wake = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, &mut guard.rx)
+                    .await
+                    .ok()
+                    .and_then(Result::ok),
+                None => (&mut guard.rx).await.ok(),
+            };
+            // Dropping the guard hands on a wake that came with the timeout
+            drop(guard);
+            match wake {
+                Some(key) => woken = Some(key),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Pops the front of the first non-empty list among `keys`.
+    fn pop_first_locked(
+        &self,
+        data: &mut HashMap<String, Entry>,
+        keys: &[&str],
+        now: Instant,
+    ) -> Result<Option<(String, StoreValue)>, StoreError> {
+        for key in keys {
+            match data.get(*key) {
+                Some(entry) if entry.is_expired(now) => self.expire_locked(data, *key),
+                Some(Entry {
+                    value: StoreValue::List(_),
+                    ..
+                }) => {
+                    if let Some(value) = self.pop_front_locked(data, key)? {
+                        return Ok(Some((key.to_string(), value)));
+                    }
+                }
+                Some(entry) => return Err(StoreError::wrong_type(key, "list", &entry.value)),
+                None => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Pops and publishes the front of the list at `key`, if it has one.
+    fn pop_front_locked(
+        &self,
+        data: &mut HashMap<String, Entry>,
+        key: &str,
+    ) -> Result<Option<StoreValue>, StoreError> {
+        let Some(entry) = data.get_mut(key) else {
+            return Ok(None);
+        };
+        let value = match &entry.value {
+            StoreValue::List(list) => match list.first() {
+                Some(value) => value.clone(),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let edit = Edit::List(ListEdit::Popped {
+            end: End::Front,
+            value: value.clone(),
+        });
+        entry.value.apply_edit(&edit);
+        let published = self.events.try_publish(|revision| ChangeEvent::Edited {
+            revision,
+            key: key.to_string(),
+            edit,
+        });
+        match published {
+            Ok(revision) => entry.revision = revision,
+            Err(error) => {
+                let undo = Edit::List(ListEdit::Pushed {
+                    end: End::Front,
+                    value,
+                });
+                entry.value.apply_edit(&undo);
+                return Err(error.into());
+            }
+        }
+        self.propagate(data, key);
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(n: i64) -> StoreValue {
+        StoreValue::Counter(n)
+    }
+
+    #[tokio::test]
+    async fn test_blocking_pop_waits_for_a_push() {
+        let store = ReactiveStore::new();
+        store.push_back("ready", job(0)).unwrap();
+        assert_eq!(
+            store.blocking_pop_front(&["empty", "ready"], None).await,
+            Ok(Some(("ready".to_string(), job(0))))
+        );
+
+        let worker = {
+            let store = store.clone();
+            tokio::spawn(async move { store.blocking_pop_front(&["a", "b"], None).await })
+        };
+        tokio::task::yield_now().await;
+        store.push_back("b", job(1)).unwrap();
+        assert_eq!(worker.await.unwrap(), Ok(Some(("b".to_string(), job(1)))));
+        assert_eq!(store.llen("b"), Ok(0));
+
+        assert_eq!(
+            store
+                .blocking_pop_front(&["a"], Some(Duration::from_millis(10)))
+                .await,
+            Ok(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocking_pop_serves_waiters_in_order() {
+        let store = ReactiveStore::new();
+        let mut workers = Vec::new();
+        for _ in 0..3 {
+            let store = store.clone();
+            workers.push(tokio::spawn(async move {
+                store.blocking_pop_front(&["jobs"], None).await
+            }));
+            // Let each worker register before the next one starts
+            tokio::task::yield_now().await;
+        }
+
+        for n in 0..3 {
+            store.push_back("jobs", job(n)).unwrap();
+        }
+        for (n, worker) in workers.into_iter().enumerate() {
+            assert_eq!(
+                worker.await.unwrap(),
+                Ok(Some(("jobs".to_string(), job(n as i64))))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropped_waiter_leaves_the_element() {
+        let store = ReactiveStore::new();
+        let mut events = store.subscribe();
+        let mut first = Box::pin(store.blocking_pop_front(&["jobs"], None));
+        let mut second = Box::pin(store.blocking_pop_front(&["jobs"], None));
+        assert!(futures_util::FutureExt::now_or_never(first.as_mut()).is_none());
+        assert!(futures_util::FutureExt::now_or_never(second.as_mut()).is_none());
+        store.push_back("jobs", job(1)).unwrap();
+        drop(first);
+        assert_eq!(store.lrange("jobs", 0, -1), Ok(vec![job(1)]));
+
+        // The element was never popped, so the only change is the push
+        let mut edits = Vec::new();
+        while let Ok(ChangeEvent::Edited { edit, .. }) = events.try_recv() {
+            edits.push(edit);
+        }
+        assert_eq!(
+            edits,
+            [Edit::List(ListEdit::Pushed {
+                end: End::Back,
+                value: job(1)
+            })]
+        );
+
+        // The wake the first waiter got went on to the second
+        assert_eq!(second.await, Ok(Some(("jobs".to_string(), job(1)))));
+        assert_eq!(store.llen("jobs"), Ok(0));
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_waiter_leaves_elements() {
+        let store = ReactiveStore::new();
+        let pending = store.blocking_pop_front(&["jobs"], Some(Duration::from_millis(5)));
+        assert_eq!(pending.await, Ok(None));
+
+        store.push_back("jobs", job(1)).unwrap();
+        assert_eq!(store.lrange("jobs", 0, -1), Ok(vec![job(1)]));
+        store.set("name", StoreValue::Text("bob".into())).unwrap();
+        assert!(matches!(
+            store.blocking_pop_front(&["name"], None).await,
+            Err(StoreError::WrongType { .. })
+        ));
+    }
+}
@@ -0,0 +1,656 @@
+use crate::reactive_store::{
+    Edit, End, ListEdit, MapEdit, SetEdit, SortedSet, SortedSetEdit, StoreValue, StreamEdit,
+    StreamId,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::ops::Range;
+use thiserror::Error;
+
+/// Bytes that do not hold a value of the expected type.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("input ends in the middle of a value")]
+    Truncated,
+    #[error("unknown tag {tag}")]
+    UnknownTag { tag: u8 },
+    #[error("text is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("length or index does not fit this platform")]
+    OutOfRange,
+    /// The input holds `len` more bytes than the value it encodes.
+    #[error("{len} bytes follow the encoded value")]
+    TrailingBytes { len: usize },
+}
+
+/**
+ * The binary form keys and values take in store logs and snapshots.
+ * Implemented for the dynamic store's keys and values, the integer key
+ * types and the usual containers, so implementing it for a custom type is
+ * mostly a matter of encoding its fields in order.
+ */
+pub trait Persist: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    /// Decodes a value from the front of `input` and advances past it.
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// Splits `len` bytes off the front of `input`.
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < len {
+        return Err(DecodeError::Truncated);
+    }
+    let (head, rest) = input.split_at(len);
+    *input = rest;
+    Ok(head)
+}
+
+fn tag(input: &mut &[u8]) -> Result<u8, DecodeError> {
+    u8::decode(input)
+}
+
+macro_rules! integers {
+    ($($ty:ty),*) => {
+        $(impl Persist for $ty {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                let bytes = take(input, std::mem::size_of::<$ty>())?;
+                Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        })*
+    };
+}
+
+integers!(u8, u16, u32, u64, u128, i16, i32, i64, i128);
+
+/// Written as a `u64`, so logs move between platforms.
+impl Persist for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        usize::try_from(u64::decode(input)?).map_err(|_| DecodeError::OutOfRange)
+    }
+}
+
+/// Written as an `i64`, so logs move between platforms.
+impl Persist for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        isize::try_from(i64::decode(input)?).map_err(|_| DecodeError::OutOfRange)
+    }
+}
+
+impl Persist for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match tag(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::UnknownTag { tag }),
+        }
+    }
+}
+
+impl Persist for f64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.to_bits().encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(f64::from_bits(u64::decode(input)?))
+    }
+}
+
+impl Persist for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = usize::decode(input)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<T: Persist> Persist for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match tag(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            tag => Err(DecodeError::UnknownTag { tag }),
+        }
+    }
+}
+
+impl<A: Persist, B: Persist> Persist for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+/// Encodes the length of `items` followed by each item.
+fn encode_all<'a, T, I>(len: usize, items: I, out: &mut Vec<u8>)
+where
+    T: Persist + 'a,
+    I: IntoIterator<Item = &'a T>,
+{
+    len.encode(out);
+    for item in items {
+        item.encode(out);
+    }
+}
+
+/// Decodes a length followed by that many items.
+fn decode_all<T: Persist, C: FromIterator<T>>(input: &mut &[u8]) -> Result<C, DecodeError> {
+    let len = usize::decode(input)?;
+    (0..len).map(|_| T::decode(input)).collect()
+}
+
+impl<T: Persist> Persist for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_all(self.len(), self, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+impl<T: Persist + Eq + Hash> Persist for HashSet<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_all(self.len(), self, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+impl<K: Persist + Eq + Hash, V: Persist> Persist for HashMap<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+impl<K: Persist + Ord, V: Persist> Persist for BTreeMap<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+impl Persist for Range<usize> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.start.encode(out);
+        self.end.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(usize::decode(input)?..usize::decode(input)?)
+    }
+}
+
+impl Persist for StreamId {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.ms.encode(out);
+        self.seq.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(StreamId::new(u64::decode(input)?, u64::decode(input)?))
+    }
+}
+
+/// Written as `(member, score)` pairs by rank.
+impl Persist for SortedSet {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for (member, score) in self.iter() {
+            member.len().encode(out);
+            out.extend_from_slice(member.as_bytes());
+            score.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut set = SortedSet::new();
+        for _ in 0..usize::decode(input)? {
+            let (member, score) = <(String, f64)>::decode(input)?;
+            set.insert(&member, score);
+        }
+        Ok(set)
+    }
+}
+
+impl Persist for StoreValue {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            StoreValue::Map(fields) => {
+                out.push(0);
+                fields.encode(out);
+            }
+            StoreValue::List(values) => {
+                out.push(1);
+                values.encode(out);
+            }
+            StoreValue::Set(members) => {
+                out.push(2);
+                members.encode(out);
+            }
+            StoreValue::Counter(value) => {
+                out.push(3);
+                value.encode(out);
+            }
+            StoreValue::Text(value) => {
+                out.push(4);
+                value.encode(out);
+            }
+            StoreValue::SortedSet(set) => {
+                out.push(5);
+                set.encode(out);
+            }
+            StoreValue::Stream(stream) => {
+                out.push(6);
+                stream.encode(out);
+            }
+            StoreValue::Bytes(bytes) => {
+                out.push(7);
+                bytes.len().encode(out);
+                out.extend_from_slice(bytes);
+            }
+            StoreValue::Float(value) => {
+                out.push(8);
+                value.encode(out);
+            }
+            StoreValue::Bool(value) => {
+                out.push(9);
+                value.encode(out);
+            }
+            StoreValue::Null => out.push(10),
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let value = match tag(input)? {
+            0 => StoreValue::Map(Persist::decode(input)?),
+            1 => StoreValue::List(Persist::decode(input)?),
+            2 => StoreValue::Set(Persist::decode(input)?),
+            3 => StoreValue::Counter(Persist::decode(input)?),
+            4 => StoreValue::Text(Persist::decode(input)?),
+            5 => StoreValue::SortedSet(Persist::decode(input)?),
+            6 => StoreValue::Stream(Persist::decode(input)?),
+            7 => {
+                let len = usize::decode(input)?;
+                StoreValue::Bytes(take(input, len)?.to_vec())
+            }
+            8 => StoreValue::Float(Persist::decode(input)?),
+            9 => StoreValue::Bool(Persist::decode(input)?),
+            10 => StoreValue::Null,
+            tag => return Err(DecodeError::UnknownTag { tag }),
+        };
+        Ok(value)
+    }
+}
+
+impl Persist for End {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(match self {
+            End::Front => 0,
+            End::Back => 1,
+        });
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match tag(input)? {
+            0 => Ok(End::Front),
+            1 => Ok(End::Back),
+            tag => Err(DecodeError::UnknownTag { tag }),
+        }
+    }
+}
+
+impl Persist for Edit {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Edit::List(edit) => {
+                out.push(0);
+                edit.encode(out);
+            }
+            Edit::Set(edit) => {
+                out.push(1);
+                edit.encode(out);
+            }
+            Edit::Map(edit) => {
+                out.push(2);
+                edit.encode(out);
+            }
+            Edit::SortedSet(edit) => {
+                out.push(3);
+                edit.encode(out);
+            }
+            Edit::Stream(edit) => {
+                out.push(4);
+                edit.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let edit = match tag(input)? {
+            0 => Edit::List(Persist::decode(input)?),
+            1 => Edit::Set(Persist::decode(input)?),
+            2 => Edit::Map(Persist::decode(input)?),
+            3 => Edit::SortedSet(Persist::decode(input)?),
+            4 => Edit::Stream(Persist::decode(input)?),
+            tag => return Err(DecodeError::UnknownTag { tag }),
+        };
+        Ok(edit)
+    }
+}
+
+impl Persist for ListEdit {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            ListEdit::Pushed { end, value } => {
+                out.push(0);
+                end.encode(out);
+                value.encode(out);
+            }
+            ListEdit::Popped { end, value } => {
+                out.push(1);
+                end.encode(out);
+                value.encode(out);
+            }
+            ListEdit::Replaced { index, value } => {
+                out.push(2);
+                index.encode(out);
+                value.encode(out);
+            }
+            ListEdit::Inserted { index, value } => {
+                out.push(3);
+                index.encode(out);
+                value.encode(out);
+            }
+            ListEdit::Removed { indices } => {
+                out.push(4);
+                indices.encode(out);
+            }
+            ListEdit::Trimmed { retained } => {
+                out.push(5);
+                retained.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let edit = match tag(input)? {
+            0 => ListEdit::Pushed {
+                end: Persist::decode(input)?,
+                value: Persist::decode(input)?,
+            },
+            1 => ListEdit::Popped {
+                end: Persist::decode(input)?,
+                value: Persist::decode(input)?,
+            },
+            2 => ListEdit::Replaced {
+                index: Persist::decode(input)?,
+                value: Persist::decode(input)?,
+            },
+            3 => ListEdit::Inserted {
+                index: Persist::decode(input)?,
+                value: Persist::decode(input)?,
+            },
+            4 => ListEdit::Removed {
+                indices: Persist::decode(input)?,
+            },
+            5 => ListEdit::Trimmed {
+                retained: Persist::decode(input)?,
+            },
+            tag => return Err(DecodeError::UnknownTag { tag }),
+        };
+        Ok(edit)
+    }
+}
+
+impl Persist for SetEdit {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let (tag, members) = match self {
+            SetEdit::Added { members } => (0, members),
+            SetEdit::Removed { members } => (1, members),
+        };
+        out.push(tag);
+        members.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match tag(input)? {
+            0 => Ok(SetEdit::Added {
+                members: Persist::decode(input)?,
+            }),
+            1 => Ok(SetEdit::Removed {
+                members: Persist::decode(input)?,
+            }),
+            tag => Err(DecodeError::UnknownTag { tag }),
+        }
+    }
+}
+
+impl Persist for MapEdit {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            MapEdit::Set { path, value } => {
+                out.push(0);
+                path.encode(out);
+                value.encode(out);
+            }
+            MapEdit::Removed { path } => {
+                out.push(1);
+                path.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match tag(input)? {
+            0 => Ok(MapEdit::Set {
+                path: Persist::decode(input)?,
+                value: Persist::decode(input)?,
+            }),
+            1 => Ok(MapEdit::Removed {
+                path: Persist::decode(input)?,
+            }),
+            tag => Err(DecodeError::UnknownTag { tag }),
+        }
+    }
+}
+
+impl Persist for SortedSetEdit {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            SortedSetEdit::Scored { members } => {
+                out.push(0);
+                members.encode(out);
+            }
+            SortedSetEdit::Removed { members } => {
+                out.push(1);
+                members.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match tag(input)? {
+            0 => Ok(SortedSetEdit::Scored {
+                members: Persist::decode(input)?,
+            }),
+            1 => Ok(SortedSetEdit::Removed {
+                members: Persist::decode(input)?,
+            }),
+            tag => Err(DecodeError::UnknownTag { tag }),
+        }
+    }
+}
+
+impl Persist for StreamEdit {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            StreamEdit::Added { id, fields } => {
+                out.push(0);
+                id.encode(out);
+                fields.encode(out);
+            }
+            StreamEdit::Trimmed { removed } => {
+                out.push(1);
+                removed.encode(out);
+            }
+            StreamEdit::GroupCreated { group, start } => {
+                out.push(2);
+                group.encode(out);
+                start.encode(out);
+            }
+            StreamEdit::Delivered {
+                group,
+                consumer,
+                ids,
+            } => {
+                out.push(3);
+                group.encode(out);
+                consumer.encode(out);
+                ids.encode(out);
+            }
+            StreamEdit::Acked { group, ids } => {
+                out.push(4);
+                group.encode(out);
+                ids.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let edit = match tag(input)? {
+            0 => StreamEdit::Added {
+                id: Persist::decode(input)?,
+                fields: Persist::decode(input)?,
+            },
+            1 => StreamEdit::Trimmed {
+                removed: Persist::decode(input)?,
+            },
+            2 => StreamEdit::GroupCreated {
+                group: Persist::decode(input)?,
+                start: Persist::decode(input)?,
+            },
+            3 => StreamEdit::Delivered {
+                group: Persist::decode(input)?,
+                consumer: Persist::decode(input)?,
+                ids: Persist::decode(input)?,
+            },
+            4 => StreamEdit::Acked {
+                group: Persist::decode(input)?,
+                ids: Persist::decode(input)?,
+            },
+            tag => return Err(DecodeError::UnknownTag { tag }),
+        };
+        Ok(edit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::ReactiveStore;
+
+    fn round_trip<T: Persist + PartialEq + std::fmt::Debug>(value: T) {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+        let mut input = out.as_slice();
+        assert_eq!(T::decode(&mut input), Ok(value));
+        assert!(input.is_empty());
+
+        // Every strict prefix is reported as truncated rather than misread
+        let mut prefix = &out[..out.len() - 1];
+        assert!(T::decode(&mut prefix).is_err());
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let store = ReactiveStore::new();
+        store
+            .xadd("events", HashMap::from([("n".into(), 1.into())]))
+            .unwrap();
+        store
+            .xgroup_create("events", "workers", StreamId::MIN)
+            .unwrap();
+        store.xreadgroup("events", "workers", "w1", 10).unwrap();
+        store
+            .zadd("scores", &[("bob", 1.5), ("amy", -2.0)])
+            .unwrap();
+
+        round_trip(StoreValue::List(vec![
+            store.get("events").unwrap(),
+            store.get("scores").unwrap(),
+            StoreValue::Map(HashMap::from([("a".into(), StoreValue::Null)])),
+            StoreValue::Set(HashSet::from(["é".to_string()])),
+            StoreValue::Bytes(vec![0, 255]),
+            StoreValue::Float(-0.5),
+            StoreValue::Bool(true),
+            StoreValue::Counter(i64::MIN),
+            StoreValue::Text(String::new()),
+        ]));
+        round_trip(Edit::List(ListEdit::Trimmed { retained: 1..3 }));
+        round_trip(Edit::Stream(StreamEdit::Delivered {
+            group: "workers".into(),
+            consumer: "w1".into(),
+            ids: vec![StreamId::new(1, 2)],
+        }));
+        round_trip((-7isize, Some(u128::MAX)));
+
+        let mut input: &[u8] = &[42];
+        assert_eq!(
+            StoreValue::decode(&mut input),
+            Err(DecodeError::UnknownTag { tag: 42 })
+        );
+    }
+}
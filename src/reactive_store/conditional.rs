@@ -0,0 +1,216 @@
+use crate::reactive_store::{Entry, ReactiveStore, StoreData, StoreError, StoreKey, StoreValue};
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::Instant;
+use thiserror::Error;
+
+/// A value together with the revision that last changed it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Versioned<V = StoreValue> {
+    pub value: V,
+    pub revision: u64,
+}
+
+/**
+ * A conditional write whose condition did not hold. `current` is what the
+ * key held at that moment, `None` if it did not exist, so the caller can
+ * retry against it without another read.
+ */
+#[derive(Debug, Error, Clone, PartialEq)]
+#[error("conditional write refused, key is at revision {}", .current.as_ref().map_or(0, |current| current.revision))]
+pub struct Conflict<V = StoreValue> {
+    pub current: Option<Versioned<V>>,
+}
+
+/// Why a conditional write did not happen.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ConditionalError<V = StoreValue> {
+    #[error(transparent)]
+    Conflict(#[from] Conflict<V>),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/**
+ * Writes that only happen if the key is in an expected state, checked and
+ * applied under the write lock. Like [`ReactiveStore::set`] they clear any
+ * TTL, and on success return the revision of the write. The revision of a
+ * key only moves when its value changes, so TTL updates do not invalidate
+ * it.
+ */
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    /// Returns the value of `key` and the revision that last changed it.
+    pub fn get_versioned<Q>(&self, key: &Q) -> Option<Versioned<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        let data = self.data.read().unwrap();
+        data.get(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(versioned)
+    }
+
+    /**
+     * Sets `key` to `new` if its current value equals `expected`, where
+     * `None` means the key must not exist.
+     */
+    pub fn compare_and_set<Q>(
+        &self,
+        key: &Q,
+        expected: Option<&V>,
+        new: V,
+    ) -> Result<u64, ConditionalError<V>>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.set_if(key, new, |entry| {
+            entry.map(|entry| &entry.value) == expected
+        })
+    }
+
+    /// Sets `key` only if it does not exist (`SET NX`).
+    pub fn set_if_absent<Q>(&self, key: &Q, value: V) -> Result<u64, ConditionalError<V>>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.set_if(key, value, |entry| entry.is_none())
+    }
+
+    /// Sets `key` only if it already exists (`SET XX`).
+    pub fn set_if_present<Q>(&self, key: &Q, value: V) -> Result<u64, ConditionalError<V>>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.set_if(key, value, |entry| entry.is_some())
+    }
+
+    /**
+     * Sets `key` only if it was last changed at `revision`, as returned by
+     * [`ReactiveStore::get_versioned`] or a previous conditional write.
+     * Revision 0 means the key must not exist.
+     */
+    pub fn set_if_revision<Q>(
+        &self,
+        key: &Q,
+        revision: u64,
+        value: V,
+    ) -> Result<u64, ConditionalError<V>>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.set_if(key, value, |entry| {
+            entry.map_or(0, |entry| entry.revision) == revision
+        })
+    }
+
+    /// Writes `value` if `condition` holds for the live entry of `key`.
+    fn set_if<Q, F>(&self, key: &Q, value: V, condition: F) -> Result<u64, ConditionalError<V>>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+        F: FnOnce(Option<&Entry<V>>) -> bool,
+    {
+        let now = Instant::now();
+        self.writing(|data| {
+            self.writable()?;
+            let live = data.get(key).filter(|entry| !entry.is_expired(now));
+            if !condition(live) {
+                let current = live.map(versioned);
+                return Err(Conflict { current }.into());
+            }
+            Ok(self.insert_locked(data, key, value, None)?)
+        })
+    }
+}
+
+fn versioned<V: Clone>(entry: &Entry<V>) -> Versioned<V> {
+    Versioned {
+        value: entry.value.clone(),
+        revision: entry.revision,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::Ttl;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_conditional_writes() {
+        let store = ReactiveStore::new();
+        let one = StoreValue::Counter(1);
+        let two = StoreValue::Counter(2);
+
+        let created = store.set_if_absent("a", one.clone()).unwrap();
+        assert_eq!(
+            store.set_if_present("b", one.clone()),
+            Err(Conflict { current: None }.into())
+        );
+        let current = Some(Versioned {
+            value: one.clone(),
+            revision: created,
+        });
+        assert_eq!(
+            store.set_if_absent("a", two.clone()),
+            Err(Conflict { current }.into())
+        );
+
+        assert!(store.compare_and_set("a", Some(&two), two.clone()).is_err());
+        let swapped = store.compare_and_set("a", Some(&one), two.clone()).unwrap();
+        assert!(store.compare_and_set("c", None, one.clone()).is_ok());
+
+        // Changing the TTL keeps the revision, changing the value moves it
+        store.touch("a", Duration::from_secs(60)).unwrap();
+        assert_eq!(store.get_versioned("a").unwrap().revision, swapped);
+        assert!(store.set_if_revision("a", created, one.clone()).is_err());
+        let rewritten = store.set_if_revision("a", swapped, one.clone()).unwrap();
+        assert_eq!(store.ttl("a"), Some(Ttl::Persistent));
+        store.incr_by("a", 1).unwrap();
+        assert!(store.get_versioned("a").unwrap().revision > rewritten);
+
+        assert!(store.set_if_revision("d", 0, one.clone()).is_ok());
+        assert!(store.set_if_revision("d", 0, one).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_compare_and_set() {
+        let store = Arc::new(ReactiveStore::new());
+        store.set("n", StoreValue::Counter(0)).unwrap();
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        let mut current = store.get_versioned("n").unwrap();
+                        loop {
+                            let StoreValue::Counter(n) = current.value else {
+                                unreachable!()
+                            };
+                            match store.set_if_revision("n", current.revision, (n + 1).into()) {
+                                Ok(_) => break,
+                                Err(ConditionalError::Conflict(conflict)) => {
+                                    current = conflict.current.unwrap()
+                                }
+                                Err(error) => panic!("{error}"),
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(store.get("n"), Some(StoreValue::Counter(400)));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::reactive_store::{ReactiveStore, StoreError, StoreValue};
+use std::ops::RangeInclusive;
+
+impl ReactiveStore {
+    /**
+     * Adds `delta` to the counter at `key` and returns the new value. A
+     * missing key counts as 0. Fails without changing anything if the key
+     * holds another type or the result does not fit in an `i64`.
+     */
+    pub fn incr_by(&self, key: &str, delta: i64) -> Result<i64, StoreError> {
+        self.update_counter(key, |current| {
+            current
+                .checked_add(delta)
+                .ok_or_else(|| StoreError::Overflow {
+                    key: key.to_string(),
+                })
+        })
+    }
+
+    /// Subtracts `delta` from the counter at `key`, like [`Self::incr_by`].
+    pub fn decr_by(&self, key: &str, delta: i64) -> Result<i64, StoreError> {
+        self.update_counter(key, |current| {
+            current
+                .checked_sub(delta)
+                .ok_or_else(|| StoreError::Overflow {
+                    key: key.to_string(),
+                })
+        })
+    }
+
+    /// Adds `delta` to the counter at `key`, stopping at `i64::MIN` or `i64::MAX`.
+    pub fn incr_by_saturating(&self, key: &str, delta: i64) -> Result<i64, StoreError> {
+        self.update_counter(key, |current| Ok(current.saturating_add(delta)))
+    }
+
+    /**
+     * Adds `delta` to the counter at `key` and clamps the result to `bounds`.
+     *
+     * Panics if `bounds` is empty.
+     */
+    pub fn incr_by_bounded(
+        &self,
+        key: &str,
+        delta: i64,
+        bounds: RangeInclusive<i64>,
+    ) -> Result<i64, StoreError> {
+        assert!(!bounds.is_empty(), "counter bounds must not be empty");
+        self.update_counter(key, |current| {
+            Ok(current
+                .saturating_add(delta)
+                .clamp(*bounds.start(), *bounds.end()))
+        })
+    }
+
+    /**
+     * Adds `delta` to the float at `key` and returns the new value. A missing
+     * key counts as 0. Fails without changing anything if the key holds
+     * another type or the result is not finite.
+     */
+    pub fn incr_by_float(&self, key: &str, delta: f64) -> Result<f64, StoreError> {
+        self.update_value(key, |current| {
+            let current = match current {
+                None => 0.0,
+                Some(StoreValue::Float(current)) => *current,
+                Some(other) => return Err(StoreError::wrong_type(key, "float", other)),
+            };
+            let new = current + delta;
+            if !new.is_finite() {
+                return Err(StoreError::Overflow {
+                    key: key.to_string(),
+                });
+            }
+            Ok((StoreValue::Float(new), new))
+        })
+    }
+
+    /**
+     * Stores `value` in the counter at `key` and returns the previous value,
+     * or `None` if the key did not exist.
+     */
+    pub fn get_and_set(&self, key: &str, value: i64) -> Result<Option<i64>, StoreError> {
+        self.update_value(key, |current| {
+            let old = match current {
+                None => None,
+                Some(StoreValue::Counter(old)) => Some(*old),
+                Some(other) => return Err(StoreError::wrong_type(key, "counter", other)),
+            };
+            Ok((StoreValue::Counter(value), old))
+        })
+    }
+
+    fn update_counter<F>(&self, key: &str, update: F) -> Result<i64, StoreError>
+    where
+        F: FnOnce(i64) -> Result<i64, StoreError>,
+    {
+        self.update_value(key, |current| {
+            let current = match current {
+                None => 0,
+                Some(StoreValue::Counter(current)) => *current,
+                Some(other) => return Err(StoreError::wrong_type(key, "counter", other)),
+            };
+            let new = update(current)?;
+            Ok((StoreValue::Counter(new), new))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::ChangeEvent;
+    use std::thread;
+
+    #[test]
+    fn test_counter_operations() {
+        let store = ReactiveStore::new();
+        let mut sub = store.subscribe();
+
+        assert_eq!(store.incr_by("hits", 5), Ok(5));
+        assert_eq!(store.decr_by("hits", 7), Ok(-2));
+        assert_eq!(store.get_and_set("hits", 10), Ok(Some(-2)));
+        assert_eq!(store.get_and_set("fresh", 1), Ok(None));
+        assert_eq!(store.incr_by_bounded("hits", 100, 0..=20), Ok(20));
+        assert_eq!(store.incr_by_bounded("hits", -100, 0..=20), Ok(0));
+
+        assert!(matches!(
+            sub.try_recv(),
+            Ok(ChangeEvent::Set {
+                old: None,
+                new: StoreValue::Counter(5),
+                ..
+            })
+        ));
+        assert!(matches!(
+            sub.try_recv(),
+            Ok(ChangeEvent::Set {
+                old: Some(StoreValue::Counter(5)),
+                new: StoreValue::Counter(-2),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_counter_overflow_and_wrong_type() {
+        let store = ReactiveStore::new();
+        store.set("max", StoreValue::Counter(i64::MAX)).unwrap();
+        store.set("name", StoreValue::Text("bob".into())).unwrap();
+        let revision = store.revision();
+
+        assert_eq!(
+            store.incr_by("max", 1),
+            Err(StoreError::Overflow { key: "max".into() })
+        );
+        assert_eq!(
+            store.decr_by("name", 1),
+            Err(StoreError::WrongType {
+                key: "name".into(),
+                expected: "counter",
+                found: "text",
+            })
+        );
+        // Failed updates leave the store untouched
+        assert_eq!(store.revision(), revision);
+        assert_eq!(store.get("max"), Some(StoreValue::Counter(i64::MAX)));
+
+        assert_eq!(store.incr_by_saturating("max", 1), Ok(i64::MAX));
+    }
+
+    #[test]
+    fn test_float_increments() {
+        let store = ReactiveStore::new();
+        assert_eq!(store.incr_by_float("ratio", 0.5), Ok(0.5));
+        assert_eq!(store.incr_by_float("ratio", 0.25), Ok(0.75));
+        assert_eq!(
+            store.incr_by_float("ratio", f64::INFINITY),
+            Err(StoreError::Overflow {
+                key: "ratio".into()
+            })
+        );
+        assert!(matches!(
+            store.incr_by("ratio", 1),
+            Err(StoreError::WrongType { found: "float", .. })
+        ));
+        assert_eq!(store.get("ratio"), Some(StoreValue::Float(0.75)));
+    }
+
+    #[test]
+    fn test_counter_increments_are_atomic_across_clones() {
+        let store = ReactiveStore::new();
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        store.incr_by("hits", 1).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(store.get("hits"), Some(StoreValue::Counter(8000)));
+    }
+}
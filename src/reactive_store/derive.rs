@@ -0,0 +1,400 @@
+use crate::reactive_store::{
+    ChangeEvent, Entry, ReactiveStore, StoreData, StoreError, StoreKey, StoreValue,
+};
+use std::any::Any;
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use thiserror::Error;
+
+/**
+ * Computes a derived value from the current values of its inputs, in the
+ * order they were registered. `None` for an input means the key is absent;
+ * returning `None` removes the derived key.
+ *
+ * It runs while the store is locked for writing, so it must not call back
+ * into the store. If it panics, keys derived before it keep their new
+ * values, the rest are left as they were, and the panic carries on in the
+ * caller of the write once the store is unlocked.
+ */
+pub type DeriveFn<V = StoreValue> = Arc<dyn Fn(&[Option<V>]) -> Option<V> + Send + Sync>;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DeriveError<K = String> {
+    #[error("deriving {target:?} from its inputs would create a dependency cycle")]
+    Cycle { target: K },
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+struct Derivation<K, V> {
+    inputs: Vec<K>,
+    compute: DeriveFn<V>,
+}
+
+/**
+ * Dependency graph of derived keys. Edges run from an input to every key
+ * derived from it, and are kept acyclic by refusing registrations that
+ * would close a loop.
+ */
+pub(crate) struct Derivations<K, V> {
+    by_target: HashMap<K, Derivation<K, V>>,
+    dependents: HashMap<K, Vec<K>>,
+    /// A panic of a compute closure, kept until the data lock is released.
+    panicked: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl<K, V> Default for Derivations<K, V> {
+    fn default() -> Self {
+        Derivations {
+            by_target: HashMap::new(),
+            dependents: HashMap::new(),
+            panicked: Mutex::new(None),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V> fmt::Debug for Derivations<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.by_target.iter().map(|(key, d)| (key, &d.inputs)))
+            .finish()
+    }
+}
+
+impl<K: StoreKey, V> Derivations<K, V> {
+    fn is_empty(&self) -> bool {
+        self.by_target.is_empty()
+    }
+
+    fn would_cycle(&self, target: &K, inputs: &[K]) -> bool {
+        // A cycle exists if the target already feeds, directly or not, one of its new inputs
+        let reachable = self.topological(&[target], true);
+        inputs
+            .iter()
+            .any(|input| input == target || reachable.contains(input))
+    }
+
+    fn insert(&mut self, target: K, derivation: Derivation<K, V>) {
+        self.remove(&target);
+        for input in &derivation.inputs {
+            let dependents = self.dependents.entry(input.clone()).or_default();
+            if !dependents.contains(&target) {
+                dependents.push(target.clone());
+            }
+        }
+        self.by_target.insert(target, derivation);
+    }
+
+    fn remove<Q>(&mut self, target: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(derivation) = self.by_target.remove(target) else {
+            return false;
+        };
+        for input in &derivation.inputs {
+            if let Some(dependents) = self.dependents.get_mut::<K>(input) {
+                dependents.retain(|dependent| dependent.borrow() != target);
+                if dependents.is_empty() {
+                    self.dependents.remove::<K>(input);
+                }
+            }
+        }
+        true
+    }
+
+    /**
+     * Every key reachable from `roots`, in an order where each key comes
+     * after all of its inputs. Roots are part of the result only when
+     * `include_roots` is set.
+     */
+    fn topological(&self, roots: &[&K], include_roots: bool) -> Vec<K> {
+        let mut visited = HashSet::new();
+        let mut order = Vec::new();
+        for root in roots {
+            self.visit(root, &mut visited, &mut order);
+        }
+        order.reverse();
+        if !include_roots {
+            order.retain(|key| !roots.contains(&key));
+        }
+        order
+    }
+
+    fn visit(&self, key: &K, visited: &mut HashSet<K>, order: &mut Vec<K>) {
+        if !visited.insert(key.clone()) {
+            return;
+        }
+        for dependent in self.dependents.get(key).into_iter().flatten() {
+            self.visit(dependent, visited, order);
+        }
+        order.push(key.clone());
+    }
+}
+
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    /**
+     * Registers `target` as a key computed from `inputs`. It is computed
+     * immediately and again whenever any input changes, is stored and
+     * published like any other key, and can itself be an input of other
+     * derived keys. Registering an existing target replaces its definition.
+     */
+    pub fn derive<Q, F>(&self, target: &Q, inputs: &[&Q], compute: F) -> Result<(), DeriveError<K>>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+        F: Fn(&[Option<V>]) -> Option<V> + Send + Sync + 'static,
+    {
+        let target = target.to_owned();
+        let inputs: Vec<K> = inputs.iter().map(|input| (*input).to_owned()).collect();
+        self.writing(|data| {
+            self.writable()?;
+            {
+                let mut derived = self.derived.write().unwrap();
+                if derived.would_cycle(&target, &inputs) {
+                    return Err(DeriveError::Cycle { target });
+                }
+                derived.insert(
+                    target.clone(),
+                    Derivation {
+                        inputs,
+                        compute: Arc::new(compute),
+                    },
+                );
+            }
+
+            let order = self.derived.read().unwrap().topological(&[&target], true);
+            self.recompute(data, &order);
+            Ok(())
+        })
+    }
+
+    /**
+     * Runs `write` with the store locked for writing. A compute closure
+     * that panicked meanwhile has its panic carried on once the lock is
+     * released, which keeps the lock from being poisoned.
+     */
+    pub(crate) fn writing<T, F>(&self, write: F) -> T
+    where
+        F: FnOnce(&mut HashMap<K, Entry<V>>) -> T,
+    {
+        let mut data = self.data.write().unwrap();
+        let output = write(&mut data);
+        let panicked = self.take_panic();
+        drop(data);
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        output
+    }
+
+    /// Takes the panic of a compute closure caught while the caller held the data lock.
+    pub(crate) fn take_panic(&self) -> Option<Box<dyn Any + Send>> {
+        self.derived.read().unwrap().panicked.lock().unwrap().take()
+    }
+
+    /**
+     * Stops recomputing `target`. Its current value is kept as a plain key.
+     * Returns false if it was not a derived key.
+     */
+    pub fn underive<Q>(&self, target: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let _data = self.data.write().unwrap();
+        self.derived.write().unwrap().remove(target)
+    }
+
+    /// Recomputes every key derived, directly or not, from `changed`.
+    pub(crate) fn propagate<Q>(&self, data: &mut HashMap<K, Entry<V>>, changed: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let order = {
+            let derived = self.derived.read().unwrap();
+            let Some((changed, _)) = derived.dependents.get_key_value(changed) else {
+                return;
+            };
+            derived.topological(&[changed], false)
+        };
+        self.recompute(data, &order);
+    }
+
+    /// Recomputes every derived key, after the whole keyspace changed.
+    pub(crate) fn propagate_all(&self, data: &mut HashMap<K, Entry<V>>) {
+        let order = {
+            let derived = self.derived.read().unwrap();
+            if derived.is_empty() {
+                return;
+            }
+            let roots: Vec<&K> = derived.by_target.keys().collect();
+            derived.topological(&roots, true)
+        };
+        self.recompute(data, &order);
+    }
+
+    fn recompute(&self, data: &mut HashMap<K, Entry<V>>, order: &[K]) {
+        let now = Instant::now();
+        let derived = self.derived.read().unwrap();
+        for target in order {
+            let Some(derivation) = derived.by_target.get(target) else {
+                continue;
+            };
+            let inputs: Vec<Option<V>> = derivation
+                .inputs
+                .iter()
+                .map(|input| {
+                    data.get(input)
+                        .filter(|entry| !entry.is_expired(now))
+                        .map(|entry| entry.value.clone())
+                })
+                .collect();
+            let old = data
+                .get(target)
+                .filter(|entry| !entry.is_expired(now))
+                .map(|entry| entry.value.clone());
+
+            let computed = panic::catch_unwind(AssertUnwindSafe(|| (derivation.compute)(&inputs)));
+            let computed = match computed {
+                Ok(computed) => computed,
+                Err(payload) => {
+                    derived.panicked.lock().unwrap().get_or_insert(payload);
+                    return;
+                }
+            };
+            match computed {
+                Some(new) if old.as_ref() != Some(&new) => {
+                    self.events.publish(|revision| {
+                        data.insert(
+                            target.clone(),
+                            Entry {
+                                value: new.clone(),
+                                expires_at: None,
+                                revision,
+                            },
+                        );
+                        ChangeEvent::Set {
+                            revision,
+                            key: target.clone(),
+                            old,
+                            new,
+                            expires_at: None,
+                        }
+                    });
+                }
+                None if data.contains_key(target) => {
+                    let entry = data.remove(target).unwrap();
+                    if !entry.is_expired(now) {
+                        self.events.publish(|revision| ChangeEvent::Removed {
+                            revision,
+                            key: target.clone(),
+                            old: entry.value,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(value: &Option<StoreValue>) -> i64 {
+        match value {
+            Some(StoreValue::Counter(n)) => *n,
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_derived_key_follows_inputs() {
+        let store = ReactiveStore::new();
+        store.set("cart:items", StoreValue::Counter(100)).unwrap();
+        store
+            .derive("cart:total", &["cart:items", "tax:rate"], |inputs| {
+                let total = counter(&inputs[0]) * (100 + counter(&inputs[1])) / 100;
+                Some(StoreValue::Counter(total))
+            })
+            .unwrap();
+        store
+            .derive("cart:label", &["cart:total"], |inputs| {
+                inputs[0].as_ref().map(|_| StoreValue::Text("ready".into()))
+            })
+            .unwrap();
+        assert_eq!(store.get("cart:total"), Some(StoreValue::Counter(100)));
+
+        let mut sub = store.subscribe();
+        store.set("tax:rate", StoreValue::Counter(20)).unwrap();
+        assert_eq!(store.get("cart:total"), Some(StoreValue::Counter(120)));
+        assert!(matches!(sub.try_recv(), Ok(ChangeEvent::Set { key, .. }) if key == "tax:rate"));
+        assert!(matches!(
+            sub.try_recv(),
+            Ok(ChangeEvent::Set { key, new: StoreValue::Counter(120), .. }) if key == "cart:total"
+        ));
+
+        // The label did not change, so nothing is published for it
+        assert!(sub.try_recv().is_err());
+
+        assert!(store.underive("cart:total"));
+        store.set("tax:rate", StoreValue::Counter(0)).unwrap();
+        assert_eq!(store.get("cart:total"), Some(StoreValue::Counter(120)));
+    }
+
+    #[test]
+    fn test_derive_rejects_cycles() {
+        let store = ReactiveStore::new();
+        store
+            .derive("b", &["a"], |inputs| inputs[0].clone())
+            .unwrap();
+        store
+            .derive("c", &["b"], |inputs| inputs[0].clone())
+            .unwrap();
+
+        assert_eq!(
+            store.derive("a", &["c"], |inputs| inputs[0].clone()),
+            Err(DeriveError::Cycle { target: "a".into() })
+        );
+        assert_eq!(
+            store.derive("d", &["d"], |inputs| inputs[0].clone()),
+            Err(DeriveError::Cycle { target: "d".into() })
+        );
+
+        store.set("a", StoreValue::Counter(1)).unwrap();
+        assert_eq!(store.get("c"), Some(StoreValue::Counter(1)));
+        store.remove("a").unwrap();
+        assert_eq!(store.get("c"), None);
+    }
+
+    #[test]
+    fn test_derive_panic_leaves_store_usable() {
+        let store = ReactiveStore::new();
+        store
+            .derive("double", &["n"], |inputs| match inputs[0] {
+                Some(StoreValue::Counter(n)) if n < 0 => panic!("bug in the derivation"),
+                Some(StoreValue::Counter(n)) => Some(StoreValue::Counter(n * 2)),
+                _ => None,
+            })
+            .unwrap();
+
+        let result =
+            panic::catch_unwind(AssertUnwindSafe(|| store.set("n", StoreValue::Counter(-1))));
+        assert!(result.is_err());
+        // The write went through, the derived key was left as it was
+        assert_eq!(store.get("n"), Some(StoreValue::Counter(-1)));
+        assert_eq!(store.get("double"), None);
+
+        store.set("n", StoreValue::Counter(2)).unwrap();
+        assert_eq!(store.get("double"), Some(StoreValue::Counter(4)));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::reactive_store::path;
+use crate::reactive_store::{SortedSet, StoreValue, Stream, StreamId};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/**
+ * An in-place change to a stored value, published as
+ * [`ChangeEvent::Edited`](crate::reactive_store::ChangeEvent::Edited)
+ * instead of a full copy of the value.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    List(ListEdit),
+    Set(SetEdit),
+    Map(MapEdit),
+    SortedSet(SortedSetEdit),
+    Stream(StreamEdit),
+}
+
+impl Edit {
+    /**
+     * Replays the edit on `value`, so a subscriber can keep a copy of the
+     * value in step with the store. A key created by an edit starts out as
+     * the empty value of its kind. Values of another kind are left as is.
+     */
+    pub fn apply(&self, value: &mut StoreValue) {
+        match (self, value) {
+            (Edit::List(edit), StoreValue::List(list)) => edit.apply(list),
+            (Edit::Set(edit), StoreValue::Set(set)) => edit.apply(set),
+            (Edit::Map(edit), value @ StoreValue::Map(_)) => edit.apply(value),
+            (Edit::SortedSet(edit), StoreValue::SortedSet(set)) => edit.apply(set),
+            (Edit::Stream(edit), StoreValue::Stream(stream)) => edit.apply(stream),
+            _ => {}
+        }
+    }
+}
+
+/// Which end of a list an element was pushed to or popped from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Front,
+    Back,
+}
+
+/// Where [`ReactiveStore::linsert`](crate::reactive_store::ReactiveStore::linsert)
+/// places the new element relative to the pivot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Before,
+    After,
+}
+
+/// An in-place change to a list. Indices refer to the list before the edit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListEdit {
+    Pushed {
+        end: End,
+        value: StoreValue,
+    },
+    Popped {
+        end: End,
+        value: StoreValue,
+    },
+    /// The element at `index` was overwritten.
+    Replaced {
+        index: usize,
+        value: StoreValue,
+    },
+    /// `value` was inserted so that it now sits at `index`.
+    Inserted {
+        index: usize,
+        value: StoreValue,
+    },
+    /// The elements at `indices`, in ascending order, were removed.
+    Removed {
+        indices: Vec<usize>,
+    },
+    /// Only the elements in `retained` were kept.
+    Trimmed {
+        retained: Range<usize>,
+    },
+}
+
+impl ListEdit {
+    pub fn apply(&self, list: &mut Vec<StoreValue>) {
+        match self {
+            ListEdit::Pushed {
+                end: End::Front,
+                value,
+            } => list.insert(0, value.clone()),
+            ListEdit::Pushed {
+                end: End::Back,
+                value,
+            } => list.push(value.clone()),
+            ListEdit::Popped {
+                end: End::Front, ..
+            } => {
+                if !list.is_empty() {
+                    list.remove(0);
+                }
+            }
+            ListEdit::Popped { end: End::Back, .. } => {
+                list.pop();
+            }
+            ListEdit::Replaced { index, value } => {
+                if let Some(slot) = list.get_mut(*index) {
+                    *slot = value.clone();
+                }
+            }
+            ListEdit::Inserted { index, value } => {
+                list.insert((*index).min(list.len()), value.clone());
+            }
+            ListEdit::Removed { indices } => {
+                let mut position = 0;
+                let mut indices = indices.iter().peekable();
+                list.retain(|_| {
+                    let removed = indices.next_if_eq(&&position).is_some();
+                    position += 1;
+                    !removed
+                });
+            }
+            ListEdit::Trimmed { retained } => {
+                list.truncate(retained.end);
+                list.drain(..retained.start.min(list.len()));
+            }
+        }
+    }
+}
+
+/// An in-place change to a set. Only members whose presence changed are listed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetEdit {
+    Added { members: Vec<String> },
+    Removed { members: Vec<String> },
+}
+
+impl SetEdit {
+    pub fn apply(&self, set: &mut HashSet<String>) {
+        match self {
+            SetEdit::Added { members } => set.extend(members.iter().cloned()),
+            SetEdit::Removed { members } => {
+                for member in members {
+                    set.remove(member);
+                }
+            }
+        }
+    }
+}
+
+/**
+ * An in-place change to a field of a map, addressed by a dot separated
+ * path as accepted by
+ * [`ReactiveStore::set_path`](crate::reactive_store::ReactiveStore::set_path).
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapEdit {
+    Set { path: String, value: StoreValue },
+    Removed { path: String },
+}
+
+impl MapEdit {
+    pub fn path(&self) -> &str {
+        match self {
+            MapEdit::Set { path, .. } | MapEdit::Removed { path } => path,
+        }
+    }
+
+    pub fn apply(&self, map: &mut StoreValue) {
+        let segments: Vec<&str> = self.path().split('.').collect();
+        match self {
+            MapEdit::Set { value, .. } => {
+                path::insert(map, &segments, value.clone());
+            }
+            MapEdit::Removed { .. } => {
+                path::remove(map, &segments);
+            }
+        }
+    }
+}
+
+/// An in-place change to a sorted set. Only members whose score changed are listed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortedSetEdit {
+    /// The members were added or given a new score.
+    Scored {
+        members: Vec<(String, f64)>,
+    },
+    Removed {
+        members: Vec<String>,
+    },
+}
+
+impl SortedSetEdit {
+    pub fn apply(&self, set: &mut SortedSet) {
+        match self {
+            SortedSetEdit::Scored { members } => {
+                for (member, score) in members {
+                    set.insert(member, *score);
+                }
+            }
+            SortedSetEdit::Removed { members } => {
+                for member in members {
+                    set.remove(member);
+                }
+            }
+        }
+    }
+}
+
+/// An in-place change to a stream or one of its consumer groups.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEdit {
+    Added {
+        id: StreamId,
+        fields: HashMap<String, StoreValue>,
+    },
+    /// The `removed` oldest entries were dropped.
+    Trimmed {
+        removed: usize,
+    },
+    GroupCreated {
+        group: String,
+        start: StreamId,
+    },
+    /// `group` delivered the entries `ids` to `consumer`.
+    Delivered {
+        group: String,
+        consumer: String,
+        ids: Vec<StreamId>,
+    },
+    Acked {
+        group: String,
+        ids: Vec<StreamId>,
+    },
+}
+
+impl StreamEdit {
+    pub fn apply(&self, stream: &mut Stream) {
+        match self {
+            StreamEdit::Added { id, fields } => stream.append(*id, fields.clone()),
+            StreamEdit::Trimmed { removed } => stream.trim_front(*removed),
+            StreamEdit::GroupCreated { group, start } => {
+                stream.create_group(group, *start);
+            }
+            StreamEdit::Delivered {
+                group,
+                consumer,
+                ids,
+            } => stream.deliver(group, consumer, ids),
+            StreamEdit::Acked { group, ids } => {
+                stream.ack(group, ids);
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use crate::reactive_store::{LogError, StoreValue};
+use thiserror::Error;
+
+/// Errors returned by operations that read and rewrite a stored value.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// The operation needs a `expected` value but `key` holds a `found` one.
+    #[error("key {key} holds a {found} value, expected a {expected}")]
+    WrongType {
+        key: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The new value of a counter, or float, does not fit its type.
+    #[error("updating {key} would overflow")]
+    Overflow { key: String },
+    #[error("key {key} does not exist")]
+    NoSuchKey { key: String },
+    #[error("index {index} is out of range for {key}")]
+    IndexOutOfRange { key: String, index: isize },
+    /// `path` runs through a value that is not a map or list, or names a missing list element.
+    #[error("path {path} does not address a field of {key}")]
+    InvalidPath { key: String, path: String },
+    #[error("score for {key} is not a number")]
+    InvalidScore { key: String },
+    #[error("stream {key} has no consumer group {group}")]
+    NoSuchGroup { key: String, group: String },
+    /// A write to the log failed, and the store refuses writes until
+    /// [`ReactiveStore::compact`](crate::reactive_store::ReactiveStore::compact)
+    /// rewrites it.
+    #[error("the store log failed, writes are refused: {reason}")]
+    LogFailed { reason: String },
+}
+
+impl StoreError {
+    pub(crate) fn wrong_type(key: &str, expected: &'static str, found: &StoreValue) -> Self {
+        StoreError::WrongType {
+            key: key.to_string(),
+            expected,
+            found: found.kind(),
+        }
+    }
+}
+
+impl From<LogError> for StoreError {
+    fn from(error: LogError) -> Self {
+        StoreError::LogFailed {
+            reason: error.to_string(),
+        }
+    }
+}
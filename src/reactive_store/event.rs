@@ -0,0 +1,271 @@
+use crate::reactive_store::filter::Routes;
+use crate::reactive_store::history::{ChangeLog, WatchError};
+use crate::reactive_store::subscription::{Outlet, OverflowPolicy, Subscription};
+use crate::reactive_store::wal::EventLog;
+use crate::reactive_store::{Edit, KeyFilter, LogError, StoreKey, StoreValue};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/**
+ * A single change published to subscribers of a store.
+ *
+ * Every event carries the store revision it produced. Revisions start at 1
+ * and increase by one for each published event, so a gap tells a subscriber
+ * that it missed something.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeEvent<K = String, V = StoreValue> {
+    /**
+     * `key` was written. `old` is `None` when the key did not exist, and
+     * `expires_at` is the deadline of the key after the write.
+     */
+    Set {
+        revision: u64,
+        key: K,
+        old: Option<V>,
+        new: V,
+        expires_at: Option<Instant>,
+    },
+    /// `key` was removed explicitly.
+    Removed { revision: u64, key: K, old: V },
+    /**
+     * The value of `key` was changed in place. `key` did not exist before if
+     * the edit created it; see [`Edit::apply`]. Only the per-type commands of
+     * the dynamic store publish edits.
+     */
+    Edited { revision: u64, key: K, edit: Edit },
+    /// `key` reached its deadline.
+    Expired { revision: u64, key: K, old: V },
+    /// The deadline of `key` changed; `None` means it is now persistent.
+    TtlChanged {
+        revision: u64,
+        key: K,
+        expires_at: Option<Instant>,
+    },
+    /// Every key was removed.
+    Cleared { revision: u64 },
+    /**
+     * A transaction changed several keys at once. Each key appears once, in
+     * the order the transaction first wrote it.
+     */
+    Committed {
+        revision: u64,
+        changes: Vec<Change<K, V>>,
+    },
+}
+
+/// One key changed by a transaction, see [`ChangeEvent::Committed`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<K = String, V = StoreValue> {
+    /// `key` was written, see [`ChangeEvent::Set`].
+    Set {
+        key: K,
+        old: Option<V>,
+        new: V,
+        expires_at: Option<Instant>,
+    },
+    /// `key` was removed.
+    Removed { key: K, old: V },
+}
+
+impl<K, V> Change<K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Set { key, .. } | Change::Removed { key, .. } => key,
+        }
+    }
+}
+
+impl<K, V> ChangeEvent<K, V> {
+    pub fn revision(&self) -> u64 {
+        match self {
+            ChangeEvent::Set { revision, .. }
+            | ChangeEvent::Removed { revision, .. }
+            | ChangeEvent::Edited { revision, .. }
+            | ChangeEvent::Expired { revision, .. }
+            | ChangeEvent::TtlChanged { revision, .. }
+            | ChangeEvent::Cleared { revision }
+            | ChangeEvent::Committed { revision, .. } => *revision,
+        }
+    }
+
+    /**
+     * The key this event is about, or `None` for store-wide events and
+     * transactions, which may concern several keys.
+     */
+    pub fn key(&self) -> Option<&K> {
+        match self {
+            ChangeEvent::Set { key, .. }
+            | ChangeEvent::Removed { key, .. }
+            | ChangeEvent::Edited { key, .. }
+            | ChangeEvent::Expired { key, .. }
+            | ChangeEvent::TtlChanged { key, .. } => Some(key),
+            ChangeEvent::Cleared { .. } | ChangeEvent::Committed { .. } => None,
+        }
+    }
+}
+
+impl<K: StoreKey, V> ChangeEvent<K, V> {
+    /**
+     * Whether the event concerns a key matching `filter`. Store-wide events
+     * concern every key, transactions any key they changed.
+     */
+    pub fn matches(&self, filter: &KeyFilter<K>) -> bool {
+        match self {
+            ChangeEvent::Committed { changes, .. } => {
+                changes.iter().any(|change| filter.matches(change.key()))
+            }
+            event => event.key().is_none_or(|key| filter.matches(key)),
+        }
+    }
+}
+
+/**
+ * Assigns revisions and delivers events to every subscription they match.
+ *
+ * Callers publish while holding the data write lock, which keeps revision
+ * order identical to the order the mutations were applied in.
+ */
+#[derive(Debug)]
+pub(crate) struct Publisher<K, V> {
+    routes: Mutex<Routes<K, V>>,
+    history: Mutex<ChangeLog<K, V>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    revision: AtomicU64,
+    /// Durable record of every event, see [`ReactiveStore::open`](crate::reactive_store::ReactiveStore::open).
+    log: Option<Arc<dyn EventLog<K, V>>>,
+}
+
+impl<K: StoreKey, V: Clone> Publisher<K, V> {
+    pub(crate) fn new(capacity: usize, policy: OverflowPolicy, history: usize) -> Self {
+        Publisher {
+            routes: Mutex::new(Routes::default()),
+            history: Mutex::new(ChangeLog::new(history)),
+            capacity,
+            policy,
+            revision: AtomicU64::new(0),
+            log: None,
+        }
+    }
+
+    /// Numbers the next event `revision + 1`.
+    pub(crate) fn starting_after(mut self, revision: u64) -> Self {
+        self.revision = AtomicU64::new(revision);
+        self
+    }
+
+    /// Appends every event to `log`.
+    pub(crate) fn with_log(mut self, log: Arc<dyn EventLog<K, V>>) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    /**
+     * Publishes a change the caller cannot take back, such as an expiry. An
+     * event the log fails to record is still delivered, and copied into a
+     * rewrite in progress, as the rewrite is what ends the failure.
+     */
+    pub(crate) fn publish<F>(&self, make: F) -> u64
+    where
+        F: FnOnce(u64) -> ChangeEvent<K, V>,
+    {
+        let mut routes = self.routes.lock().unwrap();
+        let revision = self.revision() + 1;
+        let event = make(revision);
+        if let Some(log) = &self.log {
+            if log.append(&event).is_err() {
+                log.copy_aside(&event);
+            }
+        }
+        self.deliver(&mut routes, revision, &event);
+        revision
+    }
+
+    /**
+     * Publishes a change only once the log has recorded it. If the log
+     * fails, the event is dropped without using up its revision and the
+     * caller undoes the change.
+     */
+    pub(crate) fn try_publish<F>(&self, make: F) -> Result<u64, LogError>
+    where
+        F: FnOnce(u64) -> ChangeEvent<K, V>,
+    {
+        let mut routes = self.routes.lock().unwrap();
+        let revision = self.revision() + 1;
+        let event = make(revision);
+        if let Some(log) = &self.log {
+            log.append(&event)?;
+        }
+        self.deliver(&mut routes, revision, &event);
+        Ok(revision)
+    }
+
+    fn deliver(&self, routes: &mut Routes<K, V>, revision: u64, event: &ChangeEvent<K, V>) {
+        self.revision.store(revision, Ordering::SeqCst);
+        self.history.lock().unwrap().push(event);
+        routes.route(event);
+    }
+
+    /// Whether a change has to be copied before it is made, so that it can be undone.
+    pub(crate) fn logged(&self) -> bool {
+        self.log.is_some()
+    }
+
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn log(&self) -> Option<&dyn EventLog<K, V>> {
+        self.log.as_deref()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub(crate) fn subscribe(&self, filter: Option<KeyFilter<K>>) -> Subscription<K, V> {
+        let mut routes = self.routes.lock().unwrap();
+        let revision = self.revision();
+        self.attach(&mut routes, filter, revision, VecDeque::new())
+    }
+
+    /**
+     * Subscribes starting after `revision`, replaying retained events first.
+     * Both the replay and the live channel are set up under the routing lock,
+     * so no event can fall between them.
+     */
+    pub(crate) fn subscribe_from(
+        &self,
+        filter: Option<KeyFilter<K>>,
+        revision: u64,
+    ) -> Result<Subscription<K, V>, WatchError> {
+        let mut routes = self.routes.lock().unwrap();
+        let current = self.revision();
+        let replay = self
+            .history
+            .lock()
+            .unwrap()
+            .since(revision, current, filter.as_ref())?;
+        Ok(self.attach(&mut routes, filter, revision, replay))
+    }
+
+    /// Creates the live channel for a new subscription starting after `revision`.
+    fn attach(
+        &self,
+        routes: &mut Routes<K, V>,
+        filter: Option<KeyFilter<K>>,
+        revision: u64,
+        replay: VecDeque<ChangeEvent<K, V>>,
+    ) -> Subscription<K, V> {
+        let (outlet, inlet) = Outlet::channel(self.policy, self.capacity);
+        routes.add(filter.clone(), outlet);
+        Subscription::new(inlet, filter, revision, replay)
+    }
+}
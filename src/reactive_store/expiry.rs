@@ -0,0 +1,158 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Notify;
+
+/**
+ * Deadline heap shared by every clone of a store and its reaper task.
+ *
+ * The heap is never searched or updated in place: overwriting a key or
+ * changing its TTL simply pushes a new deadline, and the reaper discards
+ * entries whose deadline no longer matches the one stored with the key.
+ */
+#[derive(Debug)]
+pub(crate) struct ExpiryQueue<K> {
+    heap: Mutex<BinaryHeap<Reverse<Scheduled<K>>>>,
+    notify: Arc<Notify>,
+    reaper_started: AtomicBool,
+}
+
+/// A key to check at `deadline`, ordered by deadline alone.
+#[derive(Debug)]
+struct Scheduled<K> {
+    deadline: Instant,
+    key: K,
+}
+
+impl<K> PartialEq for Scheduled<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl<K> Eq for Scheduled<K> {}
+
+impl<K> PartialOrd for Scheduled<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K> Ord for Scheduled<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+impl<K> ExpiryQueue<K> {
+    pub(crate) fn new() -> Self {
+        ExpiryQueue {
+            heap: Mutex::new(BinaryHeap::new()),
+            notify: Arc::new(Notify::new()),
+            reaper_started: AtomicBool::new(false),
+        }
+    }
+
+    /**
+     * Schedules `key` to be checked at `deadline`.
+     * The reaper is only woken when the new deadline becomes the earliest one.
+     */
+    pub(crate) fn schedule(&self, key: K, deadline: Instant) {
+        self.schedule_all([key], deadline);
+    }
+
+    /// Schedules every one of `keys` at the same `deadline`, under one lock.
+    pub(crate) fn schedule_all<I>(&self, keys: I, deadline: Instant)
+    where
+        I: IntoIterator<Item = K>,
+    {
+        let mut heap = self.heap.lock().unwrap();
+        let is_earliest = match heap.peek() {
+            Some(Reverse(head)) => deadline < head.deadline,
+            None => true,
+        };
+        heap.extend(
+            keys.into_iter()
+                .map(|key| Reverse(Scheduled { deadline, key })),
+        );
+        drop(heap);
+
+        if is_earliest {
+            self.notify.notify_one();
+        }
+    }
+
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        let heap = self.heap.lock().unwrap();
+        heap.peek().map(|Reverse(head)| head.deadline)
+    }
+
+    /**
+     * Pops at most `limit` entries whose deadline is at or before `now`,
+     * in deadline order.
+     */
+    pub(crate) fn pop_due(&self, now: Instant, limit: usize) -> Vec<(Instant, K)> {
+        let mut heap = self.heap.lock().unwrap();
+        let mut due = Vec::new();
+        while due.len() < limit {
+            match heap.peek() {
+                Some(Reverse(head)) if head.deadline <= now => {
+                    let Reverse(Scheduled { deadline, key }) = heap.pop().unwrap();
+                    due.push((deadline, key));
+                }
+                _ => break,
+            }
+        }
+        due
+    }
+
+    pub(crate) fn notifier(&self) -> Arc<Notify> {
+        self.notify.clone()
+    }
+
+    /**
+     * Returns true exactly once, for the caller that should spawn the reaper.
+     */
+    pub(crate) fn claim_reaper(&self) -> bool {
+        self.reaper_started
+            .compare_exchange(
+                false,
+                true,
+                atomic::Ordering::AcqRel,
+                atomic::Ordering::Acquire,
+            )
+            .is_ok()
+    }
+}
+
+impl<K> Drop for ExpiryQueue<K> {
+    fn drop(&mut self) {
+        // Wake the reaper so it notices the store is gone and exits.
+        self.notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pop_due_in_deadline_order() {
+        let queue = ExpiryQueue::new();
+        let now = Instant::now();
+
+        queue.schedule("late", now + Duration::from_secs(60));
+        queue.schedule("b", now - Duration::from_millis(1));
+        queue.schedule("a", now - Duration::from_millis(2));
+
+        assert_eq!(queue.next_deadline(), Some(now - Duration::from_millis(2)));
+
+        let due = queue.pop_due(now, 10);
+        let keys: Vec<_> = due.iter().map(|(_, key)| *key).collect();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(queue.next_deadline(), Some(now + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::reactive_store::subscription::Outlet;
+use crate::reactive_store::{ChangeEvent, StoreKey};
+use std::collections::HashMap;
+
+/**
+ * Selects the keys a filtered subscription receives events for. Prefixes
+ * and patterns apply to the text of a key, see [`StoreKey::as_text`].
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyFilter<K = String> {
+    /// Exactly this key.
+    Key(K),
+    /// Every key starting with this prefix.
+    Prefix(String),
+    /// Keys matching a glob where `*` matches any run of characters
+    /// (including none) and `?` matches exactly one.
+    Pattern(String),
+}
+
+impl<K: StoreKey> KeyFilter<K> {
+    pub fn matches(&self, key: &K) -> bool {
+        match self {
+            KeyFilter::Key(expected) => key == expected,
+            KeyFilter::Prefix(prefix) => key
+                .as_text()
+                .is_some_and(|key| key.starts_with(prefix.as_str())),
+            KeyFilter::Pattern(pattern) => {
+                key.as_text().is_some_and(|key| glob_match(pattern, key))
+            }
+        }
+    }
+}
+
+/**
+ * Iterative glob matcher that backtracks only to the most recent `*`,
+ * which keeps it linear in practice and avoids recursion on long keys.
+ */
+fn glob_match(pattern: &str, key: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let key: Vec<char> = key.chars().collect();
+    let (mut p, mut k) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while k < key.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, k));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character and retry
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    k = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/**
+ * Server-side routing table that hands each event to the subscriptions it
+ * matches.
+ *
+ * Exact-key subscribers are found with a single hash lookup, so writes to
+ * unrelated keys cost nothing per subscriber. Prefix and pattern filters are
+ * checked one by one. Outlets whose receivers have been dropped are pruned
+ * while routing.
+ */
+#[derive(Debug)]
+pub(crate) struct Routes<K, V> {
+    all: Vec<Outlet<K, V>>,
+    keys: HashMap<K, Vec<Outlet<K, V>>>,
+    filters: Vec<(KeyFilter<K>, Outlet<K, V>)>,
+}
+
+impl<K, V> Default for Routes<K, V> {
+    fn default() -> Self {
+        Routes {
+            all: Vec::new(),
+            keys: HashMap::new(),
+            filters: Vec::new(),
+        }
+    }
+}
+
+impl<K: StoreKey, V: Clone> Routes<K, V> {
+    pub(crate) fn add(&mut self, filter: Option<KeyFilter<K>>, outlet: Outlet<K, V>) {
+        match filter {
+            None => self.all.push(outlet),
+            Some(KeyFilter::Key(key)) => self.keys.entry(key).or_default().push(outlet),
+            Some(filter) => self.filters.push((filter, outlet)),
+        }
+    }
+
+    pub(crate) fn route(&mut self, event: &ChangeEvent<K, V>) {
+        send_all(&mut self.all, event);
+
+        match event {
+            ChangeEvent::Committed { changes, .. } => {
+                // Each key appears once, so no subscriber gets the batch twice
+                for change in changes {
+                    self.route_key(change.key(), event);
+                }
+            }
+            event => match event.key() {
+                Some(key) => self.route_key(key, event),
+                // Store-wide events concern every subscriber
+                None => self.keys.retain(|_, outlets| send_all(outlets, event)),
+            },
+        }
+        self.filters
+            .retain(|(filter, outlet)| !event.matches(filter) || outlet.send(event));
+    }
+
+    /// Sends `event` to the exact-key subscribers of `key`.
+    fn route_key(&mut self, key: &K, event: &ChangeEvent<K, V>) {
+        if let Some(outlets) = self.keys.get_mut(key) {
+            if !send_all(outlets, event) {
+                self.keys.remove(key);
+            }
+        }
+    }
+}
+
+/// Sends to every live outlet, returning false once none remain.
+fn send_all<K: Clone, V: Clone>(
+    outlets: &mut Vec<Outlet<K, V>>,
+    event: &ChangeEvent<K, V>,
+) -> bool {
+    outlets.retain(|outlet| outlet.send(event));
+    !outlets.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("session:*:token", "session:42:token"));
+        assert!(glob_match("session:*:token", "session::token"));
+        assert!(!glob_match("session:*:token", "session:42:tokens"));
+        assert!(glob_match("user:?", "user:7"));
+        assert!(!glob_match("user:?", "user:42"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "axxbyybzc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+    }
+
+    #[test]
+    fn test_key_filter_matches() {
+        let key = |key: &str| key.to_string();
+        assert!(KeyFilter::Key(key("user:42")).matches(&key("user:42")));
+        assert!(!KeyFilter::Key(key("user:42")).matches(&key("user:420")));
+        assert!(KeyFilter::Prefix("user:".into()).matches(&key("user:420")));
+        assert!(!KeyFilter::Prefix("user:".into()).matches(&key("session:1")));
+
+        // Keys without text only match exact filters
+        assert!(KeyFilter::Key(42u64).matches(&42));
+        assert!(!KeyFilter::<u64>::Prefix("4".into()).matches(&42));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::reactive_store::{ChangeEvent, KeyFilter, StoreKey};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum WatchError {
+    /// The events after `requested` have already been trimmed from the change
+    /// log; the oldest one still retained is `oldest`.
+    #[error("revision {requested} has been compacted, oldest retained revision is {oldest}")]
+    Compacted { requested: u64, oldest: u64 },
+    /// `requested` has not been published yet; the latest revision is `current`.
+    #[error("revision {requested} is ahead of the current revision {current}")]
+    FutureRevision { requested: u64, current: u64 },
+}
+
+/**
+ * Bounded log of the most recent change events, oldest first.
+ * Once full, every new event trims the oldest one.
+ */
+#[derive(Debug)]
+pub(crate) struct ChangeLog<K, V> {
+    events: VecDeque<ChangeEvent<K, V>>,
+    capacity: usize,
+}
+
+impl<K: StoreKey, V: Clone> ChangeLog<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        ChangeLog {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub(crate) fn push(&mut self, event: &ChangeEvent<K, V>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event.clone());
+    }
+
+    /**
+     * Returns the retained events after `revision` that match `filter`.
+     * `current` is the latest published revision, used to tell an empty
+     * replay apart from one whose events have been trimmed. A `revision`
+     * past `current` fails, as following from it would skip live events.
+     */
+    pub(crate) fn since(
+        &self,
+        revision: u64,
+        current: u64,
+        filter: Option<&KeyFilter<K>>,
+    ) -> Result<VecDeque<ChangeEvent<K, V>>, WatchError> {
+        if revision > current {
+            return Err(WatchError::FutureRevision {
+                requested: revision,
+                current,
+            });
+        }
+        if revision == current {
+            return Ok(VecDeque::new());
+        }
+
+        let oldest = self
+            .events
+            .front()
+            .map_or(current + 1, |event| event.revision());
+        if oldest > revision + 1 {
+            return Err(WatchError::Compacted {
+                requested: revision,
+                oldest,
+            });
+        }
+
+        // Revisions in the log are contiguous, so the first event to replay is at a known offset
+        let start = (revision + 1 - oldest) as usize;
+        Ok(self
+            .events
+            .range(start..)
+            .filter(|event| filter.is_none_or(|filter| event.matches(filter)))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::StoreValue;
+
+    fn cleared(revision: u64) -> ChangeEvent {
+        ChangeEvent::Cleared { revision }
+    }
+
+    #[test]
+    fn test_change_log_trims_and_replays() {
+        let mut log = ChangeLog::<String, StoreValue>::new(3);
+        for revision in 1..=5 {
+            log.push(&cleared(revision));
+        }
+
+        let replay = log.since(2, 5, None).unwrap();
+        let revisions: Vec<_> = replay.iter().map(ChangeEvent::revision).collect();
+        assert_eq!(revisions, vec![3, 4, 5]);
+
+        assert_eq!(log.since(5, 5, None).unwrap().len(), 0);
+        assert_eq!(
+            log.since(6, 5, None),
+            Err(WatchError::FutureRevision {
+                requested: 6,
+                current: 5
+            })
+        );
+        assert_eq!(
+            log.since(1, 5, None),
+            Err(WatchError::Compacted {
+                requested: 1,
+                oldest: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_disabled_change_log_is_always_compacted() {
+        let mut log = ChangeLog::<String, StoreValue>::new(0);
+        log.push(&cleared(1));
+
+        assert!(log.since(1, 1, None).unwrap().is_empty());
+        assert_eq!(
+            log.since(0, 1, None),
+            Err(WatchError::Compacted {
+                requested: 0,
+                oldest: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use crate::reactive_store::{ReactiveStore, SortedSet, StoreError, StoreValue, StreamId};
+use serde::de::{self, DeserializeOwned, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self as ser, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+/// Errors converting between stored values and JSON.
+#[derive(Debug, Error)]
+pub enum JsonError {
+    /// Bytes and streams have no JSON counterpart.
+    #[error("{kind} values have no JSON representation")]
+    Unrepresentable { kind: &'static str },
+    #[error("{value} has no JSON representation")]
+    NonFinite { value: f64 },
+    /// Integers above `i64::MAX` do not fit a counter.
+    #[error("{value} does not fit a counter")]
+    OutOfRange { value: u64 },
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/**
+ * JSON documents stored as regular values, so that path operations,
+ * counters and edits work on them like on any other map.
+ */
+impl ReactiveStore {
+    /// Stores `value` at `key` in its JSON form, see [`StoreValue::try_from`].
+    pub fn set_json<T>(&self, key: &str, value: &T) -> Result<(), JsonError>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = serde_json::to_value(value)?;
+        self.set(key, value.try_into()?)?;
+        Ok(())
+    }
+
+    /// Reads the value at `key` back as a `T`, through its JSON form.
+    pub fn get_json<T>(&self, key: &str) -> Result<Option<T>, JsonError>
+    where
+        T: DeserializeOwned,
+    {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+        let value = Value::try_from(value)?;
+        Ok(Some(serde_json::from_value(value)?))
+    }
+}
+
+/**
+ * Objects become maps, arrays lists and strings text. Integers become
+ * counters and fail to convert above `i64::MAX`, other numbers become
+ * floats. Converting the result back gives the same document.
+ */
+impl TryFrom<Value> for StoreValue {
+    type Error = JsonError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let value = match value {
+            Value::Null => StoreValue::Null,
+            Value::Bool(value) => StoreValue::Bool(value),
+            Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+                (Some(value), _) => StoreValue::Counter(value),
+                (None, Some(value)) => return Err(JsonError::OutOfRange { value }),
+                (None, None) => StoreValue::Float(number.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(value) => StoreValue::Text(value),
+            Value::Array(values) => StoreValue::List(
+                values
+                    .into_iter()
+                    .map(StoreValue::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(fields) => StoreValue::Map(
+                fields
+                    .into_iter()
+                    .map(|(field, value)| Ok((field, value.try_into()?)))
+                    .collect::<Result<_, JsonError>>()?,
+            ),
+        };
+        Ok(value)
+    }
+}
+
+/**
+ * The inverse of the conversion from JSON. Sets become arrays of their
+ * members in sorted order, counters integers, and sorted sets arrays of
+ * `[member, score]` pairs by rank. Bytes, streams and non-finite floats
+ * cannot be converted.
+ */
+impl TryFrom<StoreValue> for Value {
+    type Error = JsonError;
+
+    fn try_from(value: StoreValue) -> Result<Self, Self::Error> {
+        let value = match value {
+            StoreValue::Map(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(field, value)| Ok((field, value.try_into()?)))
+                    .collect::<Result<Map<_, _>, JsonError>>()?,
+            ),
+            StoreValue::List(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            StoreValue::Set(members) => {
+                let mut members: Vec<String> = members.into_iter().collect();
+                members.sort_unstable();
+                Value::Array(members.into_iter().map(Value::String).collect())
+            }
+            StoreValue::Counter(value) => Value::from(value),
+            StoreValue::Text(value) => Value::String(value),
+            StoreValue::SortedSet(set) => Value::Array(
+                set.iter()
+                    .map(|(member, score)| Ok(Value::Array(vec![member.into(), float(score)?])))
+                    .collect::<Result<_, JsonError>>()?,
+            ),
+            StoreValue::Float(value) => float(value)?,
+            StoreValue::Bool(value) => Value::Bool(value),
+            StoreValue::Null => Value::Null,
+            other @ (StoreValue::Bytes(_) | StoreValue::Stream(_)) => {
+                return Err(JsonError::Unrepresentable { kind: other.kind() })
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn float(value: f64) -> Result<Value, JsonError> {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or(JsonError::NonFinite { value })
+}
+
+/**
+ * Serialized the way it converts to JSON, so `serde_json::to_value` agrees
+ * with `Value::try_from` and fails on the same values, bytes aside. Those
+ * are serialized as bytes, which formats with a byte type keep as is and
+ * JSON writes as an array of numbers.
+ */
+impl Serialize for StoreValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            StoreValue::Map(fields) => serializer.collect_map(fields),
+            StoreValue::List(values) => serializer.collect_seq(values),
+            StoreValue::Set(members) => {
+                let mut members: Vec<&String> = members.iter().collect();
+                members.sort_unstable();
+                serializer.collect_seq(members)
+            }
+            StoreValue::Counter(value) => serializer.serialize_i64(*value),
+            StoreValue::Text(value) => serializer.serialize_str(value),
+            StoreValue::SortedSet(set) => set.serialize(serializer),
+            StoreValue::Float(value) => serializer.serialize_f64(finite(*value)?),
+            StoreValue::Bool(value) => serializer.serialize_bool(*value),
+            StoreValue::Null => serializer.serialize_unit(),
+            StoreValue::Bytes(value) => serializer.serialize_bytes(value),
+            other @ StoreValue::Stream(_) => Err(ser::Error::custom(JsonError::Unrepresentable {
+                kind: other.kind(),
+            })),
+        }
+    }
+}
+
+/**
+ * Deserialized the way it converts from JSON, with byte strings becoming
+ * bytes. The kind of value is taken from the input, so this needs a self
+ * describing format such as JSON or CBOR. Formats that rely on the type to
+ * tell what comes next, such as bincode, cannot deserialize it.
+ */
+impl<'de> Deserialize<'de> for StoreValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(StoreValueVisitor)
+    }
+}
+
+struct StoreValueVisitor;
+
+impl<'de> Visitor<'de> for StoreValueVisitor {
+    type Value = StoreValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<StoreValue, E> {
+        Ok(StoreValue::Bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<StoreValue, E> {
+        Ok(StoreValue::Counter(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<StoreValue, E> {
+        i64::try_from(value)
+            .map(StoreValue::Counter)
+            .map_err(|_| E::custom(JsonError::OutOfRange { value }))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<StoreValue, E> {
+        Ok(StoreValue::Float(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<StoreValue, E> {
+        Ok(StoreValue::Text(value.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<StoreValue, E> {
+        Ok(StoreValue::Text(value))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<StoreValue, E> {
+        Ok(StoreValue::Bytes(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<StoreValue, E> {
+        Ok(StoreValue::Bytes(value))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<StoreValue, E> {
+        Ok(StoreValue::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<StoreValue, E> {
+        Ok(StoreValue::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<StoreValue, D::Error> {
+        StoreValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<StoreValue, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(StoreValue::List(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<StoreValue, A::Error> {
+        let mut fields = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((field, value)) = map.next_entry()? {
+            fields.insert(field, value);
+        }
+        Ok(StoreValue::Map(fields))
+    }
+}
+
+/// Fails on infinities and NaN, which JSON has no numbers for.
+fn finite<E: ser::Error>(value: f64) -> Result<f64, E> {
+    match value.is_finite() {
+        true => Ok(value),
+        false => Err(E::custom(JsonError::NonFinite { value })),
+    }
+}
+
+/**
+ * Serialized as a sequence of `(member, score)` pairs by rank. Infinite
+ * scores fail, as for [`StoreValue`].
+ */
+impl Serialize for SortedSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for (member, score) in self.iter() {
+            seq.serialize_element(&(member, finite(score)?))?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for SortedSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pairs = Vec::<(String, f64)>::deserialize(deserializer)?;
+        let mut set = SortedSet::new();
+        for (member, score) in pairs {
+            if score.is_nan() {
+                return Err(de::Error::custom("sorted set scores must not be NaN"));
+            }
+            set.insert(&member, score);
+        }
+        Ok(set)
+    }
+}
+
+/// Serialized as `"<ms>-<seq>"`, which also lets IDs key JSON objects.
+impl Serialize for StreamId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for StreamId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        let (ms, seq) = id
+            .split_once('-')
+            .and_then(|(ms, seq)| Some((ms.parse().ok()?, seq.parse().ok()?)))
+            .ok_or_else(|| de::Error::custom(format!("invalid stream ID {id}")))?;
+        Ok(StreamId::new(ms, seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn test_json_round_trip() {
+        let document = json!({
+            "name": "bob",
+            "age": 42,
+            "height": 1.8,
+            "admin": false,
+            "manager": null,
+            "tags": ["a", 1, 2.5, [true]],
+        });
+        let value = StoreValue::try_from(document.clone()).unwrap();
+        let StoreValue::Map(fields) = &value else {
+            panic!("expected a map, got {value:?}");
+        };
+        assert_eq!(fields["age"], StoreValue::Counter(42));
+        assert_eq!(fields["height"], StoreValue::Float(1.8));
+        assert_eq!(fields["manager"], StoreValue::Null);
+        assert_eq!(Value::try_from(value).unwrap(), document);
+
+        // Integers beyond a counter are refused rather than rounded
+        assert!(matches!(
+            StoreValue::try_from(json!([u64::MAX])),
+            Err(JsonError::OutOfRange { value: u64::MAX })
+        ));
+    }
+
+    #[test]
+    fn test_json_mapping_of_store_only_types() {
+        let set = StoreValue::Set(HashSet::from(["b".to_string(), "a".to_string()]));
+        assert_eq!(Value::try_from(set).unwrap(), json!(["a", "b"]));
+        assert_eq!(
+            Value::try_from(StoreValue::Counter(i64::MIN)).unwrap(),
+            json!(i64::MIN)
+        );
+
+        let mut scores = SortedSet::new();
+        scores.insert("bob", 2.0);
+        scores.insert("amy", 3.5);
+        assert_eq!(
+            Value::try_from(StoreValue::SortedSet(scores)).unwrap(),
+            json!([["bob", 2.0], ["amy", 3.5]])
+        );
+
+        assert!(matches!(
+            Value::try_from(StoreValue::Bytes(vec![1])),
+            Err(JsonError::Unrepresentable { kind: "bytes" })
+        ));
+        assert!(matches!(
+            Value::try_from(StoreValue::List(vec![StoreValue::Float(f64::INFINITY)])),
+            Err(JsonError::NonFinite { .. })
+        ));
+    }
+
+    #[test]
+    fn test_set_and_get_json() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct User {
+            name: String,
+            visits: i64,
+        }
+
+        let store = ReactiveStore::new();
+        let user = User {
+            name: "bob".into(),
+            visits: 1,
+        };
+        store.set_json("user:1", &user).unwrap();
+        store.incr_path("user:1", "visits", 1).unwrap();
+        assert_eq!(
+            store.get_json::<User>("user:1").unwrap(),
+            Some(User { visits: 2, ..user })
+        );
+        assert!(store.get_json::<User>("missing").unwrap().is_none());
+        store.set("name", StoreValue::Text("bob".into())).unwrap();
+        assert!(matches!(
+            store.get_json::<User>("name"),
+            Err(JsonError::Serde(_))
+        ));
+    }
+
+    #[test]
+    fn test_serde_matches_the_json_conversions() {
+        let mut scores = SortedSet::new();
+        scores.insert("bob", 1.5);
+        let value = StoreValue::Map(HashMap::from([
+            ("text".to_string(), StoreValue::Text("x".into())),
+            ("count".to_string(), StoreValue::Counter(1)),
+            (
+                "set".to_string(),
+                StoreValue::Set(HashSet::from(["a".into()])),
+            ),
+            ("scores".to_string(), StoreValue::SortedSet(scores)),
+            (
+                "list".to_string(),
+                vec![StoreValue::Null, 2.5.into()].into(),
+            ),
+        ]));
+        let document = Value::try_from(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&value).unwrap(), document);
+        assert_eq!(
+            serde_json::from_value::<StoreValue>(document.clone()).unwrap(),
+            StoreValue::try_from(document).unwrap()
+        );
+
+        let store = ReactiveStore::new();
+        store
+            .set_json("text", &StoreValue::Text("x".into()))
+            .unwrap();
+        assert_eq!(store.get("text"), Some(StoreValue::Text("x".into())));
+
+        assert_eq!(
+            serde_json::to_value(StoreValue::Bytes(vec![1])).unwrap(),
+            json!([1])
+        );
+        assert!(serde_json::to_value(StoreValue::Float(f64::NAN)).is_err());
+        let mut unbounded = SortedSet::new();
+        unbounded.insert("top", f64::INFINITY);
+        assert!(serde_json::to_value(&unbounded).is_err());
+        assert!(serde_json::from_str::<StoreValue>(&u64::MAX.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_serde_round_trip_through_cbor() {
+        let value = StoreValue::Map(HashMap::from([
+            ("raw".to_string(), StoreValue::Bytes(vec![0, 1, 255])),
+            (
+                "list".to_string(),
+                vec![
+                    StoreValue::Counter(-3),
+                    StoreValue::Float(2.5),
+                    StoreValue::Bool(true),
+                    StoreValue::Null,
+                    StoreValue::Text("x".into()),
+                ]
+                .into(),
+            ),
+        ]));
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&value, &mut bytes).unwrap();
+        let read: StoreValue = ciborium::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(read, value);
+    }
+}
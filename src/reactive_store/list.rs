@@ -0,0 +1,338 @@
+use crate::reactive_store::{Edit, End, ListEdit, Position, ReactiveStore, StoreError, StoreValue};
+use std::ops::Range;
+
+/**
+ * List commands. Each one runs under the store's write lock and publishes a
+ * single [`ListEdit`] describing what changed rather than the whole list.
+ *
+ * Indices follow the usual convention that negative values count from the
+ * end, `-1` being the last element. Pushing to a missing key creates the
+ * list; popping the last element leaves an empty list behind.
+ */
+impl ReactiveStore {
+    /// Prepends `value` and returns the new length of the list.
+    pub fn push_front(&self, key: &str, value: StoreValue) -> Result<usize, StoreError> {
+        self.push(key, End::Front, value)
+    }
+
+    /// Appends `value` and returns the new length of the list.
+    pub fn push_back(&self, key: &str, value: StoreValue) -> Result<usize, StoreError> {
+        self.push(key, End::Back, value)
+    }
+
+    pub fn pop_front(&self, key: &str) -> Result<Option<StoreValue>, StoreError> {
+        self.pop(key, End::Front)
+    }
+
+    pub fn pop_back(&self, key: &str) -> Result<Option<StoreValue>, StoreError> {
+        self.pop(key, End::Back)
+    }
+
+    /// Number of elements in the list, 0 if the key does not exist.
+    pub fn llen(&self, key: &str) -> Result<usize, StoreError> {
+        Ok(self.read_list(key, |list| list.len())?.unwrap_or(0))
+    }
+
+    /// The elements from `start` to `stop`, both inclusive.
+    pub fn lrange(
+        &self,
+        key: &str,
+        start: isize,
+        stop: isize,
+    ) -> Result<Vec<StoreValue>, StoreError> {
+        let range = self.read_list(key, |list| {
+            list[resolve_range(start, stop, list.len())].to_vec()
+        })?;
+        Ok(range.unwrap_or_default())
+    }
+
+    pub fn lindex(&self, key: &str, index: isize) -> Result<Option<StoreValue>, StoreError> {
+        let value = self.read_list(key, |list| {
+            resolve(index, list.len()).map(|index| list[index].clone())
+        })?;
+        Ok(value.flatten())
+    }
+
+    /// Overwrites the element at `index`, which must exist.
+    pub fn lset(&self, key: &str, index: isize, value: StoreValue) -> Result<(), StoreError> {
+        let updated = self.edit_list(key, false, |list| {
+            let Some(index) = resolve(index, list.len()) else {
+                return Err(StoreError::IndexOutOfRange {
+                    key: key.to_string(),
+                    index,
+                });
+            };
+            list[index] = value.clone();
+            Ok(((), Some(ListEdit::Replaced { index, value })))
+        })?;
+        updated.ok_or_else(|| StoreError::NoSuchKey {
+            key: key.to_string(),
+        })
+    }
+
+    /// Keeps only the elements from `start` to `stop`, both inclusive.
+    pub fn ltrim(&self, key: &str, start: isize, stop: isize) -> Result<(), StoreError> {
+        self.edit_list(key, false, |list| {
+            let retained = resolve_range(start, stop, list.len());
+            if retained == (0..list.len()) {
+                return Ok(((), None));
+            }
+            list.truncate(retained.end);
+            list.drain(..retained.start);
+            Ok(((), Some(ListEdit::Trimmed { retained })))
+        })?;
+        Ok(())
+    }
+
+    /**
+     * Inserts `value` next to the first element equal to `pivot` and returns
+     * the new length, or `None` if the key or the pivot does not exist.
+     */
+    pub fn linsert(
+        &self,
+        key: &str,
+        position: Position,
+        pivot: &StoreValue,
+        value: StoreValue,
+    ) -> Result<Option<usize>, StoreError> {
+        let len = self.edit_list(key, false, |list| {
+            let Some(found) = list.iter().position(|element| element == pivot) else {
+                return Ok((None, None));
+            };
+            let index = match position {
+                Position::Before => found,
+                Position::After => found + 1,
+            };
+            list.insert(index, value.clone());
+            Ok((Some(list.len()), Some(ListEdit::Inserted { index, value })))
+        })?;
+        Ok(len.flatten())
+    }
+
+    /**
+     * Removes elements equal to `value` and returns how many were removed:
+     * the first `count` from the front when `count` is positive, the last
+     * `-count` when it is negative, and all of them when it is 0.
+     */
+    pub fn lrem(&self, key: &str, count: isize, value: &StoreValue) -> Result<usize, StoreError> {
+        let removed = self.edit_list(key, false, |list| {
+            let matching = list
+                .iter()
+                .enumerate()
+                .filter(|(_, element)| *element == value)
+                .map(|(index, _)| index);
+            let limit = match count {
+                0 => usize::MAX,
+                count => count.unsigned_abs(),
+            };
+            let mut indices: Vec<usize> = if count < 0 {
+                matching.rev().take(limit).collect()
+            } else {
+                matching.take(limit).collect()
+            };
+            if indices.is_empty() {
+                return Ok((0, None));
+            }
+            indices.sort_unstable();
+
+            let removed = indices.len();
+            let edit = ListEdit::Removed { indices };
+            edit.apply(list);
+            Ok((removed, Some(edit)))
+        })?;
+        Ok(removed.unwrap_or(0))
+    }
+
+    fn push(&self, key: &str, end: End, value: StoreValue) -> Result<usize, StoreError> {
+        let len = self.edit_list(key, true, |list| {
+            match end {
+                End::Front => list.insert(0, value.clone()),
+                End::Back => list.push(value.clone()),
+            }
+            Ok((list.len(), Some(ListEdit::Pushed { end, value })))
+        })?;
+        Ok(len.unwrap_or_default())
+    }
+
+    fn pop(&self, key: &str, end: End) -> Result<Option<StoreValue>, StoreError> {
+        let popped = self.edit_list(key, false, |list| {
+            let value = match end {
+                End::Front if !list.is_empty() => Some(list.remove(0)),
+                End::Front => None,
+                End::Back => list.pop(),
+            };
+            let edit = value.clone().map(|value| ListEdit::Popped { end, value });
+            Ok((value, edit))
+        })?;
+        Ok(popped.flatten())
+    }
+
+    /**
+     * Runs `edit` on the list at `key`, creating an empty one first if
+     * `create` is set. Returns `None` if the key does not exist.
+     */
+    fn edit_list<T, F>(&self, key: &str, create: bool, edit: F) -> Result<Option<T>, StoreError>
+    where
+        F: FnOnce(&mut Vec<StoreValue>) -> Result<(T, Option<ListEdit>), StoreError>,
+    {
+        let create = create.then(|| StoreValue::List(Vec::new()));
+        self.edit_value(key, create, |value| match value {
+            None => Ok((None, None)),
+            Some(StoreValue::List(list)) => {
+                let (output, edit) = edit(list)?;
+                Ok((Some(output), edit.map(Edit::List)))
+            }
+            Some(other) => Err(StoreError::wrong_type(key, "list", other)),
+        })
+    }
+
+    fn read_list<T, F>(&self, key: &str, read: F) -> Result<Option<T>, StoreError>
+    where
+        F: FnOnce(&[StoreValue]) -> T,
+    {
+        self.read_value(key, |value| match value {
+            StoreValue::List(list) => Ok(read(list)),
+            other => Err(StoreError::wrong_type(key, "list", other)),
+        })
+        .transpose()
+    }
+}
+
+/// Resolves a possibly negative `index` into a list of `len` elements.
+fn resolve(index: isize, len: usize) -> Option<usize> {
+    let index = if index < 0 {
+        index.checked_add_unsigned(len)?
+    } else {
+        index
+    };
+    usize::try_from(index).ok().filter(|index| *index < len)
+}
+
+/// Resolves an inclusive `start..=stop` range, clamped to a list of `len` elements.
+pub(crate) fn resolve_range(start: isize, stop: isize, len: usize) -> Range<usize> {
+    let clamp = |index: isize| {
+        if index < 0 {
+            len.saturating_sub(index.unsigned_abs())
+        } else {
+            index.unsigned_abs()
+        }
+    };
+    let start = clamp(start);
+    let end = if stop < 0 && stop.unsigned_abs() > len {
+        0
+    } else {
+        (clamp(stop) + 1).min(len)
+    };
+    if start >= end {
+        return 0..0;
+    }
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::ChangeEvent;
+
+    fn text(value: &str) -> StoreValue {
+        StoreValue::Text(value.into())
+    }
+
+    fn texts(values: &[&str]) -> Vec<StoreValue> {
+        values.iter().map(|value| text(value)).collect()
+    }
+
+    #[test]
+    fn test_list_operations() {
+        let store = ReactiveStore::new();
+        assert_eq!(store.push_back("jobs", text("b")), Ok(1));
+        assert_eq!(store.push_back("jobs", text("c")), Ok(2));
+        assert_eq!(store.push_front("jobs", text("a")), Ok(3));
+        assert_eq!(store.lrange("jobs", 0, -1), Ok(texts(&["a", "b", "c"])));
+        assert_eq!(store.lrange("jobs", -2, 10), Ok(texts(&["b", "c"])));
+        assert_eq!(store.lindex("jobs", -1), Ok(Some(text("c"))));
+        assert_eq!(store.lindex("jobs", 3), Ok(None));
+
+        assert_eq!(
+            store.linsert("jobs", Position::After, &text("a"), text("x")),
+            Ok(Some(4))
+        );
+        assert_eq!(
+            store.linsert("jobs", Position::Before, &text("z"), text("x")),
+            Ok(None)
+        );
+        store.lset("jobs", -1, text("x")).unwrap();
+        assert_eq!(
+            store.lrange("jobs", 0, -1),
+            Ok(texts(&["a", "x", "b", "x"]))
+        );
+        assert_eq!(store.lrem("jobs", -1, &text("x")), Ok(1));
+        assert_eq!(store.lrange("jobs", 0, -1), Ok(texts(&["a", "x", "b"])));
+
+        store.ltrim("jobs", 1, -1).unwrap();
+        assert_eq!(store.pop_front("jobs"), Ok(Some(text("x"))));
+        assert_eq!(store.pop_back("jobs"), Ok(Some(text("b"))));
+        assert_eq!(store.pop_back("jobs"), Ok(None));
+        assert_eq!(store.llen("jobs"), Ok(0));
+        assert_eq!(store.pop_front("missing"), Ok(None));
+    }
+
+    #[test]
+    fn test_list_edits_replay_onto_a_copy() {
+        let store = ReactiveStore::new();
+        let mut sub = store.subscribe();
+
+        for value in ["a", "b", "a", "c", "a", "d"] {
+            store.push_back("list", text(value)).unwrap();
+        }
+        store.push_front("list", text("z")).unwrap();
+        store.lrem("list", 2, &text("a")).unwrap();
+        store
+            .linsert("list", Position::Before, &text("d"), text("y"))
+            .unwrap();
+        store.lset("list", 0, text("w")).unwrap();
+        store.ltrim("list", 1, -2).unwrap();
+        store.pop_front("list").unwrap();
+
+        let mut copy = StoreValue::List(Vec::new());
+        while let Ok(event) = sub.try_recv() {
+            let ChangeEvent::Edited { edit, .. } = event else {
+                panic!("expected an edit, got {event:?}");
+            };
+            edit.apply(&mut copy);
+        }
+        assert_eq!(Some(copy), store.get("list"));
+    }
+
+    #[test]
+    fn test_list_errors() {
+        let store = ReactiveStore::new();
+        store.set("name", text("bob")).unwrap();
+        store.push_back("list", text("a")).unwrap();
+        let revision = store.revision();
+
+        assert_eq!(
+            store.push_back("name", text("a")),
+            Err(StoreError::WrongType {
+                key: "name".into(),
+                expected: "list",
+                found: "text",
+            })
+        );
+        assert_eq!(
+            store.lset("list", 1, text("b")),
+            Err(StoreError::IndexOutOfRange {
+                key: "list".into(),
+                index: 1,
+            })
+        );
+        assert_eq!(
+            store.lset("missing", 0, text("b")),
+            Err(StoreError::NoSuchKey {
+                key: "missing".into()
+            })
+        );
+        assert_eq!(store.revision(), revision);
+        assert_eq!(store.get("missing"), None);
+    }
+}
@@ -0,0 +1,1367 @@
+mod batch;
+mod blocking;
+mod codec;
+mod conditional;
+mod counter;
+mod derive;
+mod edit;
+mod error;
+mod event;
+mod expiry;
+mod filter;
+mod history;
+#[cfg(feature = "serde")]
+mod json;
+mod list;
+mod path;
+mod set;
+mod snapshot;
+mod sorted_set;
+mod stream;
+mod stream_value;
+mod subscription;
+mod transaction;
+mod value;
+mod wal;
+
+use crate::reactive_store::blocking::BlockedPops;
+pub use crate::reactive_store::codec::{DecodeError, Persist};
+pub use crate::reactive_store::conditional::{ConditionalError, Conflict, Versioned};
+use crate::reactive_store::derive::Derivations;
+pub use crate::reactive_store::derive::{DeriveError, DeriveFn};
+pub use crate::reactive_store::edit::{
+    Edit, End, ListEdit, MapEdit, Position, SetEdit, SortedSetEdit, StreamEdit,
+};
+pub use crate::reactive_store::error::StoreError;
+use crate::reactive_store::event::Publisher;
+pub use crate::reactive_store::event::{Change, ChangeEvent};
+use crate::reactive_store::expiry::ExpiryQueue;
+pub use crate::reactive_store::filter::KeyFilter;
+pub use crate::reactive_store::history::WatchError;
+#[cfg(feature = "serde")]
+pub use crate::reactive_store::json::JsonError;
+pub use crate::reactive_store::snapshot::SnapshotError;
+pub use crate::reactive_store::sorted_set::SortedSet;
+pub use crate::reactive_store::stream::{
+    Batch, Debounce, DistinctUntilChanged, EventStream, FilterKey, MapValue, Throttle,
+};
+pub use crate::reactive_store::stream_value::{
+    PendingEntry, Stream, StreamEntry, StreamId, StreamTrim,
+};
+pub use crate::reactive_store::subscription::{
+    OverflowPolicy, RecvError, Subscription, TryRecvError,
+};
+pub use crate::reactive_store::transaction::{ExecError, Transaction, TransactionAborted, Watch};
+use crate::reactive_store::wal::EventLog;
+pub use crate::reactive_store::wal::{
+    CompactionTrigger, FsyncPolicy, LogConfig, LogError, Recovery,
+};
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::mem;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Notify;
+
+/// Maximum number of keys the reaper expires under a single write lock.
+const REAP_BATCH: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub enum StoreValue {
+    Map(HashMap<String, StoreValue>),
+    List(Vec<StoreValue>),
+    Set(HashSet<String>),
+    Counter(i64),
+    Text(String),
+    SortedSet(SortedSet),
+    Stream(Stream),
+    Bytes(Vec<u8>),
+    Float(f64),
+    Bool(bool),
+    Null,
+}
+
+impl StoreValue {
+    /// Name of the variant, as reported by [`StoreError::WrongType`].
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StoreValue::Map(_) => "map",
+            StoreValue::List(_) => "list",
+            StoreValue::Set(_) => "set",
+            StoreValue::Counter(_) => "counter",
+            StoreValue::Text(_) => "text",
+            StoreValue::SortedSet(_) => "sorted set",
+            StoreValue::Stream(_) => "stream",
+            StoreValue::Bytes(_) => "bytes",
+            StoreValue::Float(_) => "float",
+            StoreValue::Bool(_) => "bool",
+            StoreValue::Null => "null",
+        }
+    }
+}
+
+/**
+ * A type usable as the key of a [`ReactiveStore`].
+ *
+ * [`KeyFilter::Prefix`] and [`KeyFilter::Pattern`] match against
+ * [`StoreKey::as_text`], so keys without a textual form are only ever
+ * selected by [`KeyFilter::Key`]. Implementing the trait for an identifier
+ * type is usually a single line: `impl StoreKey for UserId {}`.
+ */
+pub trait StoreKey: Clone + Eq + Hash + Debug + Send + Sync + 'static {
+    fn as_text(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl StoreKey for String {
+    fn as_text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+macro_rules! integer_keys {
+    ($($ty:ty),*) => {
+        $(impl StoreKey for $ty {})*
+    };
+}
+
+integer_keys!(u16, u32, u64, u128, usize, i16, i32, i64, i128, isize);
+
+/**
+ * A type usable as the value of a [`ReactiveStore`]. Values are compared to
+ * tell writes that change nothing apart, and cloned into every event.
+ *
+ * The provided methods are how [`StoreValue`] takes part in edits and
+ * blocking pops. Other types keep the defaults, so implementing the trait
+ * is usually a single line: `impl StoreData for Session {}`.
+ */
+pub trait StoreData: Clone + PartialEq + Send + Sync + 'static {
+    /// Applies `edit`, as when replaying a log. Only [`StoreValue`] is edited.
+    fn apply_edit(&mut self, _edit: &Edit) {}
+
+    /// What a key created by `edit` holds before it applies, if edits create keys.
+    fn created_by(_edit: &Edit) -> Option<Self> {
+        None
+    }
+
+    /// The length of a list, for waking callers of [`ReactiveStore::blocking_pop_front`].
+    fn list_len(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl StoreData for StoreValue {
+    fn apply_edit(&mut self, edit: &Edit) {
+        edit.apply(self);
+    }
+
+    fn created_by(edit: &Edit) -> Option<Self> {
+        let empty = match edit {
+            Edit::List(_) => StoreValue::List(Vec::new()),
+            Edit::Set(_) => StoreValue::Set(HashSet::new()),
+            Edit::Map(_) => StoreValue::Map(HashMap::new()),
+            Edit::SortedSet(_) => StoreValue::SortedSet(SortedSet::new()),
+            Edit::Stream(_) => StoreValue::Stream(Stream::default()),
+        };
+        Some(empty)
+    }
+
+    fn list_len(&self) -> Option<usize> {
+        match self {
+            StoreValue::List(list) => Some(list.len()),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! plain_data {
+    ($($ty:ty),*) => {
+        $(impl StoreData for $ty {})*
+    };
+}
+
+plain_data!(bool, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, String);
+
+impl<T: StoreData> StoreData for Vec<T> {}
+
+impl<T: StoreData> StoreData for Option<T> {}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Entry<V = StoreValue> {
+    pub(crate) value: V,
+    pub(crate) expires_at: Option<Instant>,
+    /// The revision that last changed the value.
+    pub(crate) revision: u64,
+}
+
+impl<V> Entry<V> {
+    fn is_expired(&self, now: Instant) -> bool {
+        matches!(self.expires_at, Some(deadline) if deadline <= now)
+    }
+}
+
+type Data<K, V> = RwLock<HashMap<K, Entry<V>>>;
+
+/// Remaining lifetime of a key, as reported by [`ReactiveStore::ttl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ttl {
+    /// The key has no deadline.
+    Persistent,
+    /// The key expires after this much more time.
+    Remaining(Duration),
+}
+
+/// Construction options for [`ReactiveStore::with_config`].
+#[derive(Debug, Clone)]
+pub struct StoreConfig {
+    /// Events each subscriber buffers before `overflow` applies.
+    pub channel_capacity: usize,
+    pub overflow: OverflowPolicy,
+    /// Number of recent events kept for [`ReactiveStore::subscribe_from`].
+    /// Zero disables the change log.
+    pub history_capacity: usize,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        StoreConfig {
+            channel_capacity: 100,
+            overflow: OverflowPolicy::DropOldest,
+            history_capacity: 1000,
+        }
+    }
+}
+
+/**
+ * An in-memory key-value store that publishes every change to its
+ * subscribers.
+ *
+ * Without type parameters this is the dynamic store, whose values are
+ * [`StoreValue`]s and which offers the list, set, counter and other
+ * per-type commands. Any [`StoreKey`] and [`StoreData`] can be used instead,
+ * for example `ReactiveStore<UserId, Session>`, with the same subscriptions,
+ * TTLs and derived keys.
+ *
+ * Writes that cannot otherwise fail only do so for a store opened with a
+ * log, once writing to it has failed, see [`ReactiveStore::open`].
+ */
+#[derive(Debug, Clone)]
+pub struct ReactiveStore<K = String, V = StoreValue> {
+    data: Arc<Data<K, V>>,
+    events: Arc<Publisher<K, V>>,
+    expiry: Arc<ExpiryQueue<K>>,
+    derived: Arc<RwLock<Derivations<K, V>>>,
+    blocked: Arc<Mutex<BlockedPops>>,
+}
+
+/// Non-owning handle to a store, used by its background tasks.
+#[derive(Debug)]
+struct WeakStore<K, V> {
+    data: Weak<Data<K, V>>,
+    events: Weak<Publisher<K, V>>,
+    expiry: Weak<ExpiryQueue<K>>,
+    derived: Weak<RwLock<Derivations<K, V>>>,
+    blocked: Weak<Mutex<BlockedPops>>,
+}
+
+impl<K, V> WeakStore<K, V> {
+    fn upgrade(&self) -> Option<ReactiveStore<K, V>> {
+        Some(ReactiveStore {
+            data: self.data.upgrade()?,
+            events: self.events.upgrade()?,
+            expiry: self.expiry.upgrade()?,
+            derived: self.derived.upgrade()?,
+            blocked: self.blocked.upgrade()?,
+        })
+    }
+}
+
+impl<K: StoreKey, V: StoreData> Default for ReactiveStore<K, V> {
+    fn default() -> Self {
+        Self::with_config(StoreConfig::default())
+    }
+}
+
+impl ReactiveStore {
+    /// Creates a dynamic store. Typed stores are created with `default` or `with_config`.
+    pub fn new() -> Self {
+        Self::with_config(StoreConfig::default())
+    }
+}
+
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    pub fn with_config(config: StoreConfig) -> Self {
+        Self::with_parts(config, HashMap::new(), 0, None)
+    }
+
+    /**
+     * Creates a store holding `data` whose next event follows `revision`,
+     * scheduling the deadlines found in `data`.
+     */
+    fn with_parts(
+        config: StoreConfig,
+        data: HashMap<K, Entry<V>>,
+        revision: u64,
+        log: Option<Arc<dyn EventLog<K, V>>>,
+    ) -> Self {
+        let mut events = Publisher::new(
+            config.channel_capacity,
+            config.overflow,
+            config.history_capacity,
+        )
+        .starting_after(revision);
+        if let Some(log) = log {
+            events = events.with_log(log);
+        }
+        let deadlines: Vec<(K, Instant)> = data
+            .iter()
+            .filter_map(|(key, entry)| Some((key.clone(), entry.expires_at?)))
+            .collect();
+        let store = ReactiveStore {
+            data: Arc::new(RwLock::new(data)),
+            events: Arc::new(events),
+            expiry: Arc::new(ExpiryQueue::new()),
+            derived: Arc::new(RwLock::new(Derivations::default())),
+            blocked: Arc::new(Mutex::new(BlockedPops::default())),
+        };
+        if !deadlines.is_empty() {
+            for (key, deadline) in deadlines {
+                store.expiry.schedule(key, deadline);
+            }
+            store.ensure_reaper();
+        }
+        store
+    }
+
+    pub fn set<Q>(&self, key: &Q, value: V) -> Result<(), StoreError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.insert(key, value, None)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        {
+            let data = self.data.read().unwrap();
+            match data.get(key) {
+                Some(entry) if !entry.is_expired(now) => return Some(entry.value.clone()),
+                Some(_) => {}
+                None => return None,
+            }
+        }
+
+        // The key is past its deadline but the reaper has not reached it yet
+        self.expire_now(key, now);
+        None
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Result<(), StoreError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        self.writing(|data| {
+            self.writable()?;
+            let backup = self.backup(data, key);
+            let Some((removed, entry)) = data.remove_entry(key) else {
+                return Ok(());
+            };
+
+            let owned = backup.is_some().then(|| removed.clone());
+            let published = if entry.is_expired(now) {
+                self.events.try_publish(|revision| ChangeEvent::Expired {
+                    revision,
+                    key: removed,
+                    old: entry.value,
+                })
+            } else {
+                self.events.try_publish(|revision| ChangeEvent::Removed {
+                    revision,
+                    key: removed,
+                    old: entry.value,
+                })
+            };
+            if let Err(error) = published {
+                if let Some(owned) = owned {
+                    Self::restore(data, owned, backup);
+                }
+                return Err(error.into());
+            }
+            self.propagate(data, key);
+            Ok(())
+        })
+    }
+
+    /**
+     * Removes every key and publishes a single `Cleared` event.
+     */
+    pub fn clear(&self) -> Result<(), StoreError> {
+        self.writing(|data| {
+            self.writable()?;
+            self.events
+                .try_publish(|revision| ChangeEvent::Cleared { revision })?;
+            data.clear();
+            self.propagate_all(data);
+            Ok(())
+        })
+    }
+
+    pub fn set_with_ttl<Q>(&self, key: &Q, value: V, ttl: Duration) -> Result<(), StoreError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let deadline = Instant::now() + ttl;
+        self.insert(key, value, Some(deadline))?;
+        self.expiry.schedule(key.to_owned(), deadline);
+        self.ensure_reaper();
+        Ok(())
+    }
+
+    /**
+     * Returns the remaining lifetime of `key`, or `None` if it does not exist.
+     */
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Ttl>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        let data = self.data.read().unwrap();
+        let entry = data.get(key).filter(|entry| !entry.is_expired(now))?;
+        Some(match entry.expires_at {
+            Some(deadline) => Ttl::Remaining(deadline.saturating_duration_since(now)),
+            None => Ttl::Persistent,
+        })
+    }
+
+    /**
+     * Removes the deadline from `key`.
+     * Returns false if the key does not exist or had no deadline.
+     */
+    pub fn persist<Q>(&self, key: &Q) -> Result<bool, StoreError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.update_deadline(key, |current| current.map(|_| None))
+    }
+
+    /**
+     * Makes `key` expire at the wall-clock time `at`. A time in the past
+     * expires the key immediately.
+     * Returns false if the key does not exist.
+     */
+    pub fn expire_at<Q>(&self, key: &Q, at: SystemTime) -> Result<bool, StoreError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let remaining = at.duration_since(SystemTime::now()).unwrap_or_default();
+        let deadline = Instant::now() + remaining;
+        self.update_deadline(key, |_| Some(Some(deadline)))
+    }
+
+    /**
+     * Restarts the sliding expiry of `key` so it lives for another `ttl`.
+     * Keys without a deadline are left persistent.
+     * Returns false if the key does not exist or had no deadline.
+     */
+    pub fn touch<Q>(&self, key: &Q, ttl: Duration) -> Result<bool, StoreError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let deadline = Instant::now() + ttl;
+        self.update_deadline(key, |current| current.map(|_| Some(deadline)))
+    }
+
+    pub fn subscribe(&self) -> Subscription<K, V> {
+        self.events.subscribe(None)
+    }
+
+    /**
+     * Resumes watching after `revision`, the last one the caller processed.
+     * Retained events newer than it are replayed before live ones. Fails with
+     * [`WatchError::Compacted`] if some of them were already trimmed from the
+     * change log, in which case the caller should resync from a snapshot, and
+     * with [`WatchError::FutureRevision`] if `revision` was never published.
+     */
+    pub fn subscribe_from(&self, revision: u64) -> Result<Subscription<K, V>, WatchError> {
+        self.events.subscribe_from(None, revision)
+    }
+
+    /// Like [`ReactiveStore::subscribe_from`], restricted to `filter`.
+    pub fn subscribe_filtered_from(
+        &self,
+        filter: KeyFilter<K>,
+        revision: u64,
+    ) -> Result<Subscription<K, V>, WatchError> {
+        self.events.subscribe_from(Some(filter), revision)
+    }
+
+    /**
+     * Subscribes to the events selected by `filter`. Routing happens when the
+     * event is published, so the receiver never wakes up for other keys.
+     * Store-wide events such as `Cleared` are delivered to every filter, and
+     * a transaction whole to every filter matching one of its keys.
+     */
+    pub fn subscribe_filtered(&self, filter: KeyFilter<K>) -> Subscription<K, V> {
+        self.events.subscribe(Some(filter))
+    }
+
+    pub fn subscribe_key<Q>(&self, key: &Q) -> Subscription<K, V>
+    where
+        Q: ToOwned<Owned = K> + ?Sized,
+    {
+        self.subscribe_filtered(KeyFilter::Key(key.to_owned()))
+    }
+
+    pub fn subscribe_prefix(&self, prefix: &str) -> Subscription<K, V> {
+        self.subscribe_filtered(KeyFilter::Prefix(prefix.to_string()))
+    }
+
+    /// Subscribes to keys matching a glob such as `session:*:token`.
+    pub fn subscribe_pattern(&self, pattern: &str) -> Subscription<K, V> {
+        self.subscribe_filtered(KeyFilter::Pattern(pattern.to_string()))
+    }
+
+    /**
+     * Recovers a subscription after [`RecvError::Lagged`]. Returns the current
+     * value of every key the subscription covers, taken atomically, and makes
+     * the subscription skip any event already reflected in that snapshot.
+     */
+    pub fn resync(&self, subscription: &mut Subscription<K, V>) -> HashMap<K, V> {
+        let now = Instant::now();
+        let data = self.data.read().unwrap();
+        // Events are published under the write lock, so this revision matches the snapshot
+        let revision = self.events.revision();
+        let snapshot = data
+            .iter()
+            .filter(|(key, entry)| {
+                !entry.is_expired(now)
+                    && subscription
+                        .filter()
+                        .is_none_or(|filter| filter.matches(key))
+            })
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect();
+        subscription.resume_after(revision);
+        snapshot
+    }
+
+    /// The revision of the most recently published change.
+    pub fn revision(&self) -> u64 {
+        self.events.revision()
+    }
+
+    /// Events each subscriber buffers before the overflow policy applies.
+    pub fn channel_capacity(&self) -> usize {
+        self.events.capacity()
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.events.policy()
+    }
+
+    fn insert<Q>(&self, key: &Q, value: V, expires_at: Option<Instant>) -> Result<(), StoreError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.writing(|data| {
+            self.writable()?;
+            self.insert_locked(data, key, value, expires_at)?;
+            Ok(())
+        })
+    }
+
+    /**
+     * Fails once writing to the log has failed, as the store would no
+     * longer match what can be replayed. Every writer calls it with the
+     * write lock held, before changing anything.
+     */
+    pub(crate) fn writable(&self) -> Result<(), StoreError> {
+        if let Some(log) = self.events.log() {
+            log.check()?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Copies the entry of `key` before a write changes it, so that
+     * [`ReactiveStore::restore`] can undo the write if the log fails to
+     * record it. Without a log nothing is copied, as nothing can fail.
+     */
+    fn backup<Q>(&self, data: &HashMap<K, Entry<V>>, key: &Q) -> Option<Option<Entry<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.events.logged().then(|| data.get(key).cloned())
+    }
+
+    /// Undoes a write to `key` with what [`ReactiveStore::backup`] copied.
+    fn restore(data: &mut HashMap<K, Entry<V>>, key: K, backup: Option<Option<Entry<V>>>) {
+        match backup {
+            Some(Some(entry)) => {
+                data.insert(key, entry);
+            }
+            Some(None) => {
+                data.remove(&key);
+            }
+            None => {}
+        }
+    }
+
+    /**
+     * Writes `key` into the already locked map and publishes the change.
+     * Returns the revision of the write.
+     */
+    fn insert_locked<Q>(
+        &self,
+        data: &mut HashMap<K, Entry<V>>,
+        key: &Q,
+        value: V,
+        expires_at: Option<Instant>,
+    ) -> Result<u64, StoreError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        let backup = self.backup(data, key);
+        let published = self.events.try_publish(|revision| {
+            let old = data
+                .insert(
+                    key.to_owned(),
+                    Entry {
+                        value: value.clone(),
+                        expires_at,
+                        revision,
+                    },
+                )
+                .filter(|entry| !entry.is_expired(now))
+                .map(|entry| entry.value);
+            ChangeEvent::Set {
+                revision,
+                key: key.to_owned(),
+                old,
+                new: value,
+                expires_at,
+            }
+        });
+        let revision = match published {
+            Ok(revision) => revision,
+            Err(error) => {
+                Self::restore(data, key.to_owned(), backup);
+                return Err(error.into());
+            }
+        };
+        self.propagate(data, key);
+        self.serve_blocked(data, key);
+        Ok(revision)
+    }
+
+    /**
+     * Replaces the value of `key` with the one `update` computes from the
+     * current value (`None` if the key is absent), in a single step under the
+     * write lock. The key keeps its deadline. Nothing is written or published
+     * when `update` fails.
+     */
+    fn update_value<Q, T, E, F>(&self, key: &Q, update: F) -> Result<T, E>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+        F: FnOnce(Option<&V>) -> Result<(V, T), E>,
+        E: From<StoreError>,
+    {
+        let now = Instant::now();
+        self.writing(|data| {
+            self.writable()?;
+            let live = data.get(key).filter(|entry| !entry.is_expired(now));
+            let expires_at = live.and_then(|entry| entry.expires_at);
+            let (value, output) = update(live.map(|entry| &entry.value))?;
+            self.insert_locked(data, key, value, expires_at)?;
+            Ok(output)
+        })
+    }
+
+    /**
+     * Changes the value of `key` in place under the write lock and publishes
+     * the [`Edit`] that `edit` reports. `edit` receives `None` if the key does
+     * not exist, unless `create` is given, in which case the key is created
+     * with that value first. A key created this way is dropped again if
+     * `edit` fails or reports no change; a failing `edit` must leave an
+     * existing value untouched.
+     */
+    fn edit_value<Q, T, F>(&self, key: &Q, create: Option<V>, edit: F) -> Result<T, StoreError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+        F: FnOnce(Option<&mut V>) -> Result<(T, Option<Edit>), StoreError>,
+    {
+        let now = Instant::now();
+        self.writing(|data| {
+            self.writable()?;
+            if matches!(data.get(key), Some(entry) if entry.is_expired(now)) {
+                self.expire_locked(data, key);
+            }
+            let backup = self.backup(data, key);
+            let created = match create {
+                Some(value) if !data.contains_key(key) => {
+                    // Not visible to anyone before the edit is published
+                    data.insert(
+                        key.to_owned(),
+                        Entry {
+                            value,
+                            expires_at: None,
+                            revision: 0,
+                        },
+                    );
+                    true
+                }
+                _ => false,
+            };
+
+            let entry = data.get_mut(key);
+            match edit(entry.map(|entry| &mut entry.value)) {
+                Ok((output, Some(edit))) => {
+                    let published = self.events.try_publish(|revision| ChangeEvent::Edited {
+                        revision,
+                        key: key.to_owned(),
+                        edit,
+                    });
+                    let revision = match published {
+                        Ok(revision) => revision,
+                        Err(error) => {
+                            Self::restore(data, key.to_owned(), backup);
+                            return Err(error.into());
+                        }
+                    };
+                    if let Some(entry) = data.get_mut(key) {
+                        entry.revision = revision;
+                    }
+                    self.propagate(data, key);
+                    self.serve_blocked(data, key);
+                    Ok(output)
+                }
+                result => {
+                    if created {
+                        data.remove(key);
+                    }
+                    result.map(|(output, _)| output)
+                }
+            }
+        })
+    }
+
+    /// Runs `read` on the live value of `key` under the read lock.
+    fn read_value<Q, T, F>(&self, key: &Q, read: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&V) -> T,
+    {
+        let data = self.data.read().unwrap();
+        data.get(key)
+            .filter(|entry| !entry.is_expired(Instant::now()))
+            .map(|entry| read(&entry.value))
+    }
+
+    /**
+     * Replaces the deadline of a live key with the one returned by `update`,
+     * which receives the current deadline and returns `None` to leave it alone.
+     */
+    fn update_deadline<Q, F>(&self, key: &Q, update: F) -> Result<bool, StoreError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(Option<Instant>) -> Option<Option<Instant>>,
+    {
+        let now = Instant::now();
+        // `None` when nothing changed, otherwise the deadline to schedule
+        let changed = self.writing(|data| {
+            self.writable()?;
+            let Some(entry) = data.get(key) else {
+                return Ok(None);
+            };
+            if entry.is_expired(now) {
+                self.expire_locked(data, key);
+                return Ok(None);
+            }
+            let Some(deadline) = update(entry.expires_at) else {
+                return Ok(None);
+            };
+
+            match deadline {
+                Some(deadline) if deadline <= now => {
+                    self.expire_locked(data, key);
+                    Ok(Some(None))
+                }
+                _ => {
+                    let owned = data.get_key_value(key).unwrap().0.clone();
+                    let entry = data.get_mut(key).unwrap();
+                    let previous = mem::replace(&mut entry.expires_at, deadline);
+                    let published = self.events.try_publish(|revision| ChangeEvent::TtlChanged {
+                        revision,
+                        key: owned.clone(),
+                        expires_at: deadline,
+                    });
+                    if let Err(error) = published {
+                        entry.expires_at = previous;
+                        return Err(StoreError::from(error));
+                    }
+                    Ok(Some(deadline.map(|deadline| (owned, deadline))))
+                }
+            }
+        })?;
+        let Some(scheduled) = changed else {
+            return Ok(false);
+        };
+
+        if let Some((key, deadline)) = scheduled {
+            self.expiry.schedule(key, deadline);
+            self.ensure_reaper();
+        }
+        Ok(true)
+    }
+
+    fn expire_now<Q>(&self, key: &Q, now: Instant)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.writing(|data| {
+            if matches!(data.get(key), Some(entry) if entry.is_expired(now)) {
+                self.expire_locked(data, key);
+            }
+        })
+    }
+
+    /// Removes `key` from the already locked map and publishes its expiry.
+    fn expire_locked<Q>(&self, data: &mut HashMap<K, Entry<V>>, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some((expired, entry)) = data.remove_entry(key) {
+            self.events.publish(|revision| ChangeEvent::Expired {
+                revision,
+                key: expired,
+                old: entry.value,
+            });
+            self.propagate(data, key);
+        }
+    }
+
+    fn expire_due(&self, due: Vec<(Instant, K)>) {
+        if due.is_empty() {
+            return;
+        }
+
+        let mut data = self.data.write().unwrap();
+        for (deadline, key) in due {
+            // Skip keys that were removed, overwritten or given a new deadline
+            if matches!(data.get(&key), Some(entry) if entry.expires_at == Some(deadline)) {
+                self.expire_locked(&mut data, &key);
+            }
+        }
+        // The reaper has no caller to carry the panic of a compute closure
+        // on to, and the panic hook has already reported it
+        drop(self.take_panic());
+    }
+
+    fn downgrade(&self) -> WeakStore<K, V> {
+        WeakStore {
+            data: Arc::downgrade(&self.data),
+            events: Arc::downgrade(&self.events),
+            expiry: Arc::downgrade(&self.expiry),
+            derived: Arc::downgrade(&self.derived),
+            blocked: Arc::downgrade(&self.blocked),
+        }
+    }
+
+    /**
+     * Starts the background reaper the first time a TTL is set from inside a
+     * tokio runtime. Outside a runtime keys still expire lazily on `get`.
+     */
+    fn ensure_reaper(&self) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        if !self.expiry.claim_reaper() {
+            return;
+        }
+
+        handle.spawn(run_reaper(self.downgrade(), self.expiry.notifier()));
+    }
+}
+
+/**
+ * A single task per store sleeps until the earliest deadline in the queue,
+ * then expires every due key in batches. It holds only weak references so it
+ * exits once the last clone of the store is dropped.
+ */
+async fn run_reaper<K: StoreKey, V: StoreData>(store: WeakStore<K, V>, notify: Arc<Notify>) {
+    loop {
+        let Some(next) = store.expiry.upgrade().map(|queue| queue.next_deadline()) else {
+            return;
+        };
+
+        match next {
+            Some(deadline) if deadline > Instant::now() => {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(deadline.into()) => {}
+                    _ = notify.notified() => {}
+                }
+            }
+            Some(_) => {}
+            None => notify.notified().await,
+        }
+
+        let Some(store) = store.upgrade() else {
+            return;
+        };
+        let due = store.expiry.pop_due(Instant::now(), REAP_BATCH);
+        let batch_full = due.len() == REAP_BATCH;
+        store.expire_due(due);
+        drop(store);
+
+        if batch_full {
+            // Let writers in between batches of a large expiry wave
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reactive_store() {
+        let store = ReactiveStore::new();
+
+        store
+            .set("key1", StoreValue::Text("value1".to_string()))
+            .unwrap();
+        assert_eq!(
+            store.get("key1"),
+            Some(StoreValue::Text("value1".to_string()))
+        );
+
+        store.set("key2", StoreValue::Counter(42)).unwrap();
+        assert_eq!(store.get("key2"), Some(StoreValue::Counter(42)));
+
+        store.remove("key1").unwrap();
+        assert_eq!(store.get("key1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_subscribe() {
+        let store = ReactiveStore::new();
+        let mut rx = store.subscribe();
+
+        store
+            .set("key1", StoreValue::Text("value1".to_string()))
+            .unwrap();
+        let msg = rx.recv().await.unwrap();
+        assert_eq!(
+            msg,
+            ChangeEvent::Set {
+                revision: 1,
+                key: "key1".to_string(),
+                old: None,
+                new: StoreValue::Text("value1".to_string()),
+                expires_at: None,
+            }
+        );
+
+        store.set("key2", StoreValue::Counter(42)).unwrap();
+        let msg = rx.recv().await.unwrap();
+        assert_eq!(msg.key().map(String::as_str), Some("key2"));
+        assert_eq!(msg.revision(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_events_for_every_mutation() {
+        let store = ReactiveStore::new();
+        let mut rx = store.subscribe();
+
+        store.set("key", StoreValue::Counter(1)).unwrap();
+        store.set("key", StoreValue::Counter(2)).unwrap();
+        store.remove("key").unwrap();
+        store.remove("key").unwrap();
+        store.set("other", StoreValue::Counter(3)).unwrap();
+        store.clear().unwrap();
+
+        let events: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent::Set {
+                    revision: 1,
+                    key: "key".to_string(),
+                    old: None,
+                    new: StoreValue::Counter(1),
+                    expires_at: None,
+                },
+                ChangeEvent::Set {
+                    revision: 2,
+                    key: "key".to_string(),
+                    old: Some(StoreValue::Counter(1)),
+                    new: StoreValue::Counter(2),
+                    expires_at: None,
+                },
+                ChangeEvent::Removed {
+                    revision: 3,
+                    key: "key".to_string(),
+                    old: StoreValue::Counter(2),
+                },
+                ChangeEvent::Set {
+                    revision: 4,
+                    key: "other".to_string(),
+                    old: None,
+                    new: StoreValue::Counter(3),
+                    expires_at: None,
+                },
+                ChangeEvent::Cleared { revision: 5 },
+            ]
+        );
+        assert_eq!(store.revision(), 5);
+        assert_eq!(store.get("other"), None);
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_filtered_subscriptions() {
+        let store = ReactiveStore::new();
+        let mut by_key = store.subscribe_key("user:42");
+        let mut by_prefix = store.subscribe_prefix("user:");
+        let mut by_pattern = store.subscribe_pattern("session:*:token");
+
+        for i in 0..1000 {
+            store
+                .set(&format!("bulk:{}", i), StoreValue::Counter(i))
+                .unwrap();
+        }
+        store.set("user:42", StoreValue::Counter(1)).unwrap();
+        store.set("user:7", StoreValue::Counter(2)).unwrap();
+        store
+            .set("session:abc:token", StoreValue::Text("t".into()))
+            .unwrap();
+        store
+            .set("session:abc:user", StoreValue::Text("u".into()))
+            .unwrap();
+        store.remove("user:42").unwrap();
+
+        let keys = |rx: &mut Subscription| {
+            std::iter::from_fn(|| rx.try_recv().ok())
+                .map(|event| event.key().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&mut by_key), vec!["user:42", "user:42"]);
+        assert_eq!(keys(&mut by_prefix), vec!["user:42", "user:7", "user:42"]);
+        assert_eq!(keys(&mut by_pattern), vec!["session:abc:token"]);
+
+        drop(by_key);
+        store.clear().unwrap();
+        assert!(matches!(
+            by_prefix.try_recv(),
+            Ok(ChangeEvent::Cleared { revision: 1006 })
+        ));
+        assert!(matches!(
+            by_pattern.try_recv(),
+            Ok(ChangeEvent::Cleared { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_lagged_subscriber_resyncs() {
+        let store = ReactiveStore::with_config(StoreConfig {
+            channel_capacity: 4,
+            overflow: OverflowPolicy::DropOldest,
+            ..StoreConfig::default()
+        });
+        let mut sub = store.subscribe_prefix("user:");
+
+        store.set("user:1", StoreValue::Counter(1)).unwrap();
+        assert_eq!(sub.recv().await.unwrap().revision(), 1);
+        for i in 0..10 {
+            store
+                .set(&format!("user:{}", i), StoreValue::Counter(i))
+                .unwrap();
+        }
+        store.set("other", StoreValue::Counter(0)).unwrap();
+
+        assert_eq!(
+            sub.recv().await,
+            Err(RecvError::Lagged {
+                missed: 6,
+                since: 1
+            })
+        );
+        let snapshot = store.resync(&mut sub);
+        assert_eq!(snapshot.len(), 10);
+        assert_eq!(snapshot["user:9"], StoreValue::Counter(9));
+
+        // Events already covered by the snapshot are skipped
+        store.set("user:1", StoreValue::Counter(100)).unwrap();
+        let event = sub.recv().await.unwrap();
+        assert_eq!(event.revision(), 13);
+        assert_eq!(sub.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_unbounded_subscriber_is_lossless() {
+        let store = ReactiveStore::with_config(StoreConfig {
+            channel_capacity: 4,
+            overflow: OverflowPolicy::Unbounded,
+            ..StoreConfig::default()
+        });
+        assert_eq!(store.overflow_policy(), OverflowPolicy::Unbounded);
+        let mut all = store.subscribe();
+        let mut one = store.subscribe_key("key7");
+
+        for i in 0..1000 {
+            store
+                .set(&format!("key{}", i), StoreValue::Counter(i))
+                .unwrap();
+        }
+
+        for revision in 1..=1000 {
+            assert_eq!(all.recv().await.unwrap().revision(), revision);
+        }
+        assert_eq!(
+            one.recv().await.unwrap().key().map(String::as_str),
+            Some("key7")
+        );
+        assert_eq!(one.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_subscribe_from_revision() {
+        let store = ReactiveStore::with_config(StoreConfig {
+            history_capacity: 5,
+            ..StoreConfig::default()
+        });
+        for i in 1..=8 {
+            store
+                .set(&format!("key{}", i % 2), StoreValue::Counter(i))
+                .unwrap();
+        }
+
+        let mut sub = store.subscribe_from(5).unwrap();
+        store.set("key9", StoreValue::Counter(9)).unwrap();
+        for revision in 6..=9 {
+            assert_eq!(sub.recv().await.unwrap().revision(), revision);
+        }
+        assert_eq!(sub.try_recv(), Err(TryRecvError::Empty));
+
+        let mut odd = store
+            .subscribe_filtered_from(KeyFilter::Key("key1".into()), 5)
+            .unwrap();
+        assert_eq!(odd.recv().await.unwrap().revision(), 7);
+        assert_eq!(odd.try_recv(), Err(TryRecvError::Empty));
+
+        assert_eq!(
+            store.subscribe_from(2).err(),
+            Some(WatchError::Compacted {
+                requested: 2,
+                oldest: 5
+            })
+        );
+        assert!(store.subscribe_from(4).is_ok());
+        assert_eq!(
+            store.subscribe_from(10).err(),
+            Some(WatchError::FutureRevision {
+                requested: 10,
+                current: 9
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_ttl() {
+        let store = ReactiveStore::new();
+        let mut sub = store.subscribe();
+
+        store
+            .set_with_ttl(
+                "temp",
+                StoreValue::Text("value".into()),
+                Duration::from_secs(1),
+            )
+            .unwrap();
+
+        let first = sub.recv().await.unwrap();
+        assert!(matches!(first, ChangeEvent::Set { key, .. } if key == "temp"));
+
+        let second = sub.recv().await.unwrap();
+        assert_eq!(
+            second,
+            ChangeEvent::Expired {
+                revision: 2,
+                key: "temp".to_string(),
+                old: StoreValue::Text("value".into()),
+            }
+        );
+
+        assert_eq!(store.get("temp"), None);
+    }
+
+    #[test]
+    fn test_reactive_store_lazy_expiry_without_runtime() {
+        let store = ReactiveStore::new();
+        let mut sub = store.subscribe();
+
+        store
+            .set_with_ttl("temp", StoreValue::Counter(1), Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(store.get("temp"), Some(StoreValue::Counter(1)));
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(store.get("temp"), None);
+
+        assert!(matches!(sub.try_recv().unwrap(), ChangeEvent::Set { .. }));
+        assert!(matches!(
+            sub.try_recv().unwrap(),
+            ChangeEvent::Expired { key, .. } if key == "temp"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_overwrite_clears_ttl() {
+        let store = ReactiveStore::new();
+
+        store
+            .set_with_ttl("a", StoreValue::Counter(1), Duration::from_millis(20))
+            .unwrap();
+        store.set("a", StoreValue::Counter(2)).unwrap();
+        store
+            .set_with_ttl("b", StoreValue::Counter(1), Duration::from_millis(20))
+            .unwrap();
+        store
+            .set_with_ttl("b", StoreValue::Counter(2), Duration::from_millis(200))
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(store.get("a"), Some(StoreValue::Counter(2)));
+        assert_eq!(store.get("b"), Some(StoreValue::Counter(2)));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(store.get("b"), None);
+    }
+
+    #[tokio::test]
+    async fn test_reactive_store_ttl_management() {
+        let store = ReactiveStore::new();
+        store.set("plain", StoreValue::Counter(1)).unwrap();
+        store
+            .set_with_ttl("temp", StoreValue::Counter(2), Duration::from_secs(60))
+            .unwrap();
+
+        assert_eq!(store.ttl("missing"), None);
+        assert_eq!(store.ttl("plain"), Some(Ttl::Persistent));
+        assert!(matches!(
+            store.ttl("temp"),
+            Some(Ttl::Remaining(left)) if left > Duration::from_secs(59)
+        ));
+
+        // touch only slides keys that already have a deadline
+        assert_eq!(store.touch("plain", Duration::from_millis(10)), Ok(false));
+        assert_eq!(store.touch("temp", Duration::from_secs(5)), Ok(true));
+        assert!(matches!(
+            store.ttl("temp"),
+            Some(Ttl::Remaining(left)) if left <= Duration::from_secs(5)
+        ));
+
+        assert_eq!(store.persist("temp"), Ok(true));
+        assert_eq!(store.persist("temp"), Ok(false));
+        assert_eq!(store.ttl("temp"), Some(Ttl::Persistent));
+
+        let mut sub = store.subscribe();
+        let soon = SystemTime::now() + Duration::from_millis(30);
+        assert_eq!(store.expire_at("plain", soon), Ok(true));
+        assert!(matches!(
+            sub.recv().await.unwrap(),
+            ChangeEvent::TtlChanged {
+                expires_at: Some(_),
+                ..
+            }
+        ));
+        assert!(matches!(
+            sub.recv().await.unwrap(),
+            ChangeEvent::Expired {
+                old: StoreValue::Counter(1),
+                ..
+            }
+        ));
+        assert_eq!(store.get("plain"), None);
+
+        assert_eq!(store.expire_at("temp", SystemTime::UNIX_EPOCH), Ok(true));
+        assert_eq!(store.get("temp"), None);
+        assert_eq!(store.expire_at("temp", soon), Ok(false));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct UserId(u32);
+
+    impl StoreKey for UserId {}
+
+    impl StoreData for Session {}
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Session {
+        token: String,
+    }
+
+    #[tokio::test]
+    async fn test_typed_store() {
+        let store: ReactiveStore<UserId, Session> = ReactiveStore::default();
+        let mut sub = store.subscribe_key(&UserId(1));
+        let session = |token: &str| Session {
+            token: token.to_string(),
+        };
+
+        store.set(&UserId(1), session("a")).unwrap();
+        store.set(&UserId(2), session("b")).unwrap();
+        store
+            .set_with_ttl(&UserId(1), session("c"), Duration::from_millis(20))
+            .unwrap();
+        assert_eq!(store.get(&UserId(1)), Some(session("c")));
+
+        assert!(matches!(
+            sub.recv().await.unwrap(),
+            ChangeEvent::Set {
+                key: UserId(1),
+                old: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            sub.recv().await.unwrap(),
+            ChangeEvent::Set { key: UserId(1), old: Some(old), .. } if old == session("a")
+        ));
+        assert!(matches!(
+            sub.recv().await.unwrap(),
+            ChangeEvent::Expired { key: UserId(1), .. }
+        ));
+        assert_eq!(store.get(&UserId(1)), None);
+
+        // Typed keys have no text, so prefix subscriptions never select them
+        let mut by_prefix = store.subscribe_prefix("");
+        store.remove(&UserId(2)).unwrap();
+        assert_eq!(by_prefix.try_recv(), Err(TryRecvError::Empty));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::reactive_store::{Edit, MapEdit, ReactiveStore, StoreError, StoreValue};
+use std::collections::HashMap;
+
+/**
+ * Field access inside map values. A path is a dot separated list of
+ * segments, such as `address.city`: map fields are addressed by name and
+ * list elements by their index, as in `orders.0.total`.
+ *
+ * Writes create missing intermediate maps, but never grow lists, and
+ * publish a [`MapEdit`] carrying the path rather than the whole value.
+ */
+impl ReactiveStore {
+    /// The field at `path`, or `None` if the key or any segment is missing.
+    pub fn get_path(&self, key: &str, path: &str) -> Result<Option<StoreValue>, StoreError> {
+        let segments = segments(key, path)?;
+        let field = self.read_value(key, |value| match value {
+            StoreValue::Map(_) => Ok(lookup(value, &segments).cloned()),
+            other => Err(StoreError::wrong_type(key, "map", other)),
+        });
+        Ok(field.transpose()?.flatten())
+    }
+
+    /**
+     * Writes `value` at `path`, creating the key and any missing maps on the
+     * way, and returns the value it replaced.
+     */
+    pub fn set_path(
+        &self,
+        key: &str,
+        path: &str,
+        value: StoreValue,
+    ) -> Result<Option<StoreValue>, StoreError> {
+        let segments = segments(key, path)?;
+        self.edit_map(key, true, |map| {
+            let old = insert(map, &segments, value.clone()).ok_or_else(|| invalid(key, path))?;
+            let edit = MapEdit::Set {
+                path: path.to_string(),
+                value,
+            };
+            Ok((old, Some(edit)))
+        })
+    }
+
+    /**
+     * Removes the field at `path` and returns it. Removing a list element
+     * shifts the ones after it.
+     */
+    pub fn remove_path(&self, key: &str, path: &str) -> Result<Option<StoreValue>, StoreError> {
+        let segments = segments(key, path)?;
+        self.edit_map(key, false, |map| {
+            let old = remove(map, &segments);
+            let edit = old.as_ref().map(|_| MapEdit::Removed {
+                path: path.to_string(),
+            });
+            Ok((old, edit))
+        })
+    }
+
+    /**
+     * Adds `delta` to the counter at `path` and returns the new value. A
+     * missing field counts as 0.
+     */
+    pub fn incr_path(&self, key: &str, path: &str, delta: i64) -> Result<i64, StoreError> {
+        let segments = segments(key, path)?;
+        self.edit_map(key, true, |map| {
+            let current = match lookup(map, &segments) {
+                None => 0,
+                Some(StoreValue::Counter(current)) => *current,
+                Some(other) => return Err(StoreError::wrong_type(key, "counter", other)),
+            };
+            let new = current
+                .checked_add(delta)
+                .ok_or_else(|| StoreError::Overflow {
+                    key: key.to_string(),
+                })?;
+            insert(map, &segments, StoreValue::Counter(new)).ok_or_else(|| invalid(key, path))?;
+            let edit = MapEdit::Set {
+                path: path.to_string(),
+                value: StoreValue::Counter(new),
+            };
+            Ok((new, Some(edit)))
+        })
+    }
+
+    fn edit_map<T, F>(&self, key: &str, create: bool, edit: F) -> Result<T, StoreError>
+    where
+        T: Default,
+        F: FnOnce(&mut StoreValue) -> Result<(T, Option<MapEdit>), StoreError>,
+    {
+        let create = create.then(|| StoreValue::Map(HashMap::new()));
+        self.edit_value(key, create, |value| match value {
+            None => Ok((T::default(), None)),
+            Some(map @ StoreValue::Map(_)) => {
+                let (output, edit) = edit(map)?;
+                Ok((output, edit.map(Edit::Map)))
+            }
+            Some(other) => Err(StoreError::wrong_type(key, "map", other)),
+        })
+    }
+}
+
+fn segments<'a>(key: &str, path: &'a str) -> Result<Vec<&'a str>, StoreError> {
+    let segments: Vec<&str> = path.split('.').collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(invalid(key, path));
+    }
+    Ok(segments)
+}
+
+fn invalid(key: &str, path: &str) -> StoreError {
+    StoreError::InvalidPath {
+        key: key.to_string(),
+        path: path.to_string(),
+    }
+}
+
+fn lookup<'a>(value: &'a StoreValue, segments: &[&str]) -> Option<&'a StoreValue> {
+    segments
+        .iter()
+        .try_fold(value, |value, segment| match value {
+            StoreValue::Map(map) => map.get(*segment),
+            StoreValue::List(list) => list.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/**
+ * Writes `new` at `segments` below `value` and returns the replaced field.
+ * Returns `None` without writing anything if the path runs through a value
+ * that is neither a map nor a list, or names a list element that does not
+ * exist.
+ */
+pub(crate) fn insert(
+    value: &mut StoreValue,
+    segments: &[&str],
+    new: StoreValue,
+) -> Option<Option<StoreValue>> {
+    let (last, parents) = segments.split_last()?;
+    let mut parent = value;
+    for segment in parents {
+        parent = match parent {
+            StoreValue::Map(map) => map
+                .entry(segment.to_string())
+                .or_insert_with(|| StoreValue::Map(HashMap::new())),
+            StoreValue::List(list) => list.get_mut(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    match parent {
+        StoreValue::Map(map) => Some(map.insert(last.to_string(), new)),
+        StoreValue::List(list) => {
+            let slot = list.get_mut(last.parse::<usize>().ok()?)?;
+            Some(Some(std::mem::replace(slot, new)))
+        }
+        _ => None,
+    }
+}
+
+/// Removes the field at `segments` below `value`, if there is one.
+pub(crate) fn remove(value: &mut StoreValue, segments: &[&str]) -> Option<StoreValue> {
+    let (last, parents) = segments.split_last()?;
+    let mut parent = value;
+    for segment in parents {
+        parent = match parent {
+            StoreValue::Map(map) => map.get_mut(*segment)?,
+            StoreValue::List(list) => list.get_mut(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    match parent {
+        StoreValue::Map(map) => map.remove(*last),
+        StoreValue::List(list) => {
+            let index = last
+                .parse::<usize>()
+                .ok()
+                .filter(|index| *index < list.len())?;
+            Some(list.remove(index))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::ChangeEvent;
+
+    #[test]
+    fn test_path_operations() {
+        let store = ReactiveStore::new();
+        let mut sub = store.subscribe();
+
+        assert_eq!(
+            store.set_path("user:1", "address.city", StoreValue::Text("Oslo".into())),
+            Ok(None)
+        );
+        store
+            .set_path(
+                "user:1",
+                "orders",
+                StoreValue::List(vec![StoreValue::Counter(5)]),
+            )
+            .unwrap();
+        assert_eq!(store.incr_path("user:1", "orders.0", 2), Ok(7));
+        assert_eq!(store.incr_path("user:1", "visits", 1), Ok(1));
+        assert_eq!(
+            store.get_path("user:1", "address.city"),
+            Ok(Some(StoreValue::Text("Oslo".into())))
+        );
+        assert_eq!(store.get_path("user:1", "address.zip"), Ok(None));
+        assert_eq!(
+            store.remove_path("user:1", "orders.0"),
+            Ok(Some(StoreValue::Counter(7)))
+        );
+        assert_eq!(store.remove_path("user:1", "orders.0"), Ok(None));
+
+        let mut edits = Vec::new();
+        while let Ok(ChangeEvent::Edited { edit, .. }) = sub.try_recv() {
+            edits.push(edit);
+        }
+        assert_eq!(
+            edits[0],
+            Edit::Map(MapEdit::Set {
+                path: "address.city".into(),
+                value: StoreValue::Text("Oslo".into()),
+            })
+        );
+
+        // Replaying the edits rebuilds the same map
+        let mut copy = StoreValue::Map(HashMap::new());
+        for edit in &edits {
+            edit.apply(&mut copy);
+        }
+        assert_eq!(Some(copy), store.get("user:1"));
+    }
+
+    #[test]
+    fn test_path_errors() {
+        let store = ReactiveStore::new();
+        store
+            .set_path("user:1", "name", StoreValue::Text("bob".into()))
+            .unwrap();
+        store.set("plain", StoreValue::Text("text".into())).unwrap();
+
+        assert_eq!(
+            store.set_path("user:1", "name.first", StoreValue::Text("b".into())),
+            Err(StoreError::InvalidPath {
+                key: "user:1".into(),
+                path: "name.first".into(),
+            })
+        );
+        assert!(matches!(
+            store.get_path("user:1", "a..b"),
+            Err(StoreError::InvalidPath { .. })
+        ));
+        assert!(matches!(
+            store.incr_path("user:1", "name", 1),
+            Err(StoreError::WrongType { found: "text", .. })
+        ));
+        assert!(matches!(
+            store.get_path("plain", "a"),
+            Err(StoreError::WrongType { found: "text", .. })
+        ));
+    }
+}
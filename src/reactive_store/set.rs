@@ -0,0 +1,293 @@
+use crate::reactive_store::{Edit, Entry, ReactiveStore, SetEdit, StoreError, StoreValue};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+enum Algebra {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/**
+ * Set commands. Single-key writes publish a [`SetEdit`] listing only the
+ * members that were actually added or removed. A missing key behaves as an
+ * empty set, and removing the last member leaves an empty set behind.
+ */
+impl ReactiveStore {
+    /// Adds `members` and returns how many of them were not present yet.
+    pub fn sadd(&self, key: &str, members: &[&str]) -> Result<usize, StoreError> {
+        self.edit_set(key, true, |set| {
+            let added: Vec<String> = members
+                .iter()
+                .filter(|member| set.insert(member.to_string()))
+                .map(|member| member.to_string())
+                .collect();
+            Ok((added.len(), SetEdit::Added { members: added }))
+        })
+    }
+
+    /// Removes `members` and returns how many of them were present.
+    pub fn srem(&self, key: &str, members: &[&str]) -> Result<usize, StoreError> {
+        self.edit_set(key, false, |set| {
+            let removed: Vec<String> = members
+                .iter()
+                .filter(|member| set.remove(**member))
+                .map(|member| member.to_string())
+                .collect();
+            Ok((removed.len(), SetEdit::Removed { members: removed }))
+        })
+    }
+
+    pub fn sismember(&self, key: &str, member: &str) -> Result<bool, StoreError> {
+        Ok(self
+            .read_set(key, |set| set.contains(member))?
+            .unwrap_or(false))
+    }
+
+    /// Number of members, 0 if the key does not exist.
+    pub fn scard(&self, key: &str) -> Result<usize, StoreError> {
+        Ok(self.read_set(key, |set| set.len())?.unwrap_or(0))
+    }
+
+    pub fn smembers(&self, key: &str) -> Result<HashSet<String>, StoreError> {
+        Ok(self.read_set(key, |set| set.clone())?.unwrap_or_default())
+    }
+
+    /// A random member, without removing it.
+    pub fn srandmember(&self, key: &str) -> Result<Option<String>, StoreError> {
+        let member = self.read_set(key, |set| random_member(set).cloned())?;
+        Ok(member.flatten())
+    }
+
+    /// Removes and returns a random member.
+    pub fn spop(&self, key: &str) -> Result<Option<String>, StoreError> {
+        self.edit_set(key, false, |set| {
+            let Some(member) = random_member(set).cloned() else {
+                return Ok((
+                    None,
+                    SetEdit::Removed {
+                        members: Vec::new(),
+                    },
+                ));
+            };
+            set.remove(&member);
+            Ok((
+                Some(member.clone()),
+                SetEdit::Removed {
+                    members: vec![member],
+                },
+            ))
+        })
+    }
+
+    /// Members present in any of `keys`.
+    pub fn sunion(&self, keys: &[&str]) -> Result<HashSet<String>, StoreError> {
+        self.combine(keys, Algebra::Union)
+    }
+
+    /// Members present in every one of `keys`.
+    pub fn sinter(&self, keys: &[&str]) -> Result<HashSet<String>, StoreError> {
+        self.combine(keys, Algebra::Intersection)
+    }
+
+    /// Members of the first of `keys` that are in none of the others.
+    pub fn sdiff(&self, keys: &[&str]) -> Result<HashSet<String>, StoreError> {
+        self.combine(keys, Algebra::Difference)
+    }
+
+    /**
+     * Stores the union of `keys` in `destination`, replacing whatever it
+     * held, and returns its size. Reading the inputs and writing the result
+     * happen under a single lock.
+     */
+    pub fn sunionstore(&self, destination: &str, keys: &[&str]) -> Result<usize, StoreError> {
+        self.combine_into(destination, keys, Algebra::Union)
+    }
+
+    /// Stores the intersection of `keys` in `destination`, like [`Self::sunionstore`].
+    pub fn sinterstore(&self, destination: &str, keys: &[&str]) -> Result<usize, StoreError> {
+        self.combine_into(destination, keys, Algebra::Intersection)
+    }
+
+    /// Stores the difference of `keys` in `destination`, like [`Self::sunionstore`].
+    pub fn sdiffstore(&self, destination: &str, keys: &[&str]) -> Result<usize, StoreError> {
+        self.combine_into(destination, keys, Algebra::Difference)
+    }
+
+    fn combine(&self, keys: &[&str], algebra: Algebra) -> Result<HashSet<String>, StoreError> {
+        let data = self.data.read().unwrap();
+        Ok(apply(algebra, &sets(&data, keys)?))
+    }
+
+    fn combine_into(
+        &self,
+        destination: &str,
+        keys: &[&str],
+        algebra: Algebra,
+    ) -> Result<usize, StoreError> {
+        self.writing(|data| {
+            self.writable()?;
+            let result = apply(algebra, &sets(data, keys)?);
+            let len = result.len();
+            self.insert_locked(data, destination, StoreValue::Set(result), None)?;
+            Ok(len)
+        })
+    }
+
+    /**
+     * Runs `edit` on the set at `key`, creating an empty one first if
+     * `create` is set. An edit that changes no member is not published.
+     */
+    fn edit_set<T, F>(&self, key: &str, create: bool, edit: F) -> Result<T, StoreError>
+    where
+        T: Default,
+        F: FnOnce(&mut HashSet<String>) -> Result<(T, SetEdit), StoreError>,
+    {
+        let create = create.then(|| StoreValue::Set(HashSet::new()));
+        self.edit_value(key, create, |value| match value {
+            None => Ok((T::default(), None)),
+            Some(StoreValue::Set(set)) => {
+                let (output, edit) = edit(set)?;
+                let changed = match &edit {
+                    SetEdit::Added { members } | SetEdit::Removed { members } => {
+                        !members.is_empty()
+                    }
+                };
+                Ok((output, changed.then_some(Edit::Set(edit))))
+            }
+            Some(other) => Err(StoreError::wrong_type(key, "set", other)),
+        })
+    }
+
+    fn read_set<T, F>(&self, key: &str, read: F) -> Result<Option<T>, StoreError>
+    where
+        F: FnOnce(&HashSet<String>) -> T,
+    {
+        self.read_value(key, |value| match value {
+            StoreValue::Set(set) => Ok(read(set)),
+            other => Err(StoreError::wrong_type(key, "set", other)),
+        })
+        .transpose()
+    }
+}
+
+/// The live sets at `keys`, `None` for keys that do not exist.
+fn sets<'a>(
+    data: &'a HashMap<String, Entry>,
+    keys: &[&str],
+) -> Result<Vec<Option<&'a HashSet<String>>>, StoreError> {
+    let now = Instant::now();
+    keys.iter()
+        .map(|key| match data.get(*key) {
+            Some(entry) if entry.is_expired(now) => Ok(None),
+            Some(Entry {
+                value: StoreValue::Set(set),
+                ..
+            }) => Ok(Some(set)),
+            Some(entry) => Err(StoreError::wrong_type(key, "set", &entry.value)),
+            None => Ok(None),
+        })
+        .collect()
+}
+
+fn apply(algebra: Algebra, sets: &[Option<&HashSet<String>>]) -> HashSet<String> {
+    let empty = HashSet::new();
+    let mut sets = sets.iter().map(|set| set.unwrap_or(&empty));
+    let Some(first) = sets.next() else {
+        return HashSet::new();
+    };
+    let mut result = first.clone();
+    for set in sets {
+        match algebra {
+            Algebra::Union => result.extend(set.iter().cloned()),
+            Algebra::Intersection => result.retain(|member| set.contains(member)),
+            Algebra::Difference => result.retain(|member| !set.contains(member)),
+        }
+    }
+    result
+}
+
+fn random_member(set: &HashSet<String>) -> Option<&String> {
+    if set.is_empty() {
+        return None;
+    }
+    // Every RandomState is freshly seeded, which is random enough to pick a member
+    let index = RandomState::new().hash_one(set.len()) as usize % set.len();
+    set.iter().nth(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::ChangeEvent;
+
+    fn members(values: &[&str]) -> HashSet<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let store = ReactiveStore::new();
+        let mut sub = store.subscribe();
+
+        assert_eq!(store.sadd("tags", &["a", "b", "a"]), Ok(2));
+        assert_eq!(store.sadd("tags", &["b"]), Ok(0));
+        assert_eq!(store.srem("tags", &["b", "z"]), Ok(1));
+        assert_eq!(store.sismember("tags", "a"), Ok(true));
+        assert_eq!(store.scard("tags"), Ok(1));
+        assert_eq!(store.smembers("missing"), Ok(HashSet::new()));
+
+        assert!(matches!(
+            sub.try_recv(),
+            Ok(ChangeEvent::Edited { edit: Edit::Set(SetEdit::Added { members }), .. })
+                if members.len() == 2
+        ));
+        // Adding a member that is already there publishes nothing
+        assert_eq!(
+            sub.try_recv(),
+            Ok(ChangeEvent::Edited {
+                revision: 2,
+                key: "tags".into(),
+                edit: Edit::Set(SetEdit::Removed {
+                    members: vec!["b".into()]
+                }),
+            })
+        );
+
+        assert_eq!(store.srandmember("tags"), Ok(Some("a".into())));
+        assert_eq!(store.spop("tags"), Ok(Some("a".into())));
+        assert_eq!(store.spop("tags"), Ok(None));
+        assert_eq!(store.get("tags"), Some(StoreValue::Set(HashSet::new())));
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let store = ReactiveStore::new();
+        store.sadd("a", &["1", "2", "3"]).unwrap();
+        store.sadd("b", &["2", "3", "4"]).unwrap();
+        store.sadd("c", &["3"]).unwrap();
+
+        assert_eq!(
+            store.sunion(&["a", "b"]),
+            Ok(members(&["1", "2", "3", "4"]))
+        );
+        assert_eq!(store.sinter(&["a", "b", "c"]), Ok(members(&["3"])));
+        assert_eq!(store.sdiff(&["a", "b"]), Ok(members(&["1"])));
+        assert_eq!(store.sinter(&["a", "missing"]), Ok(HashSet::new()));
+
+        assert_eq!(store.sdiffstore("a", &["a", "c"]), Ok(2));
+        assert_eq!(store.smembers("a"), Ok(members(&["1", "2"])));
+        assert_eq!(store.sinterstore("out", &["a", "b"]), Ok(1));
+        assert_eq!(store.smembers("out"), Ok(members(&["2"])));
+
+        store.set("name", StoreValue::Text("bob".into())).unwrap();
+        assert!(matches!(
+            store.sunionstore("out", &["a", "name"]),
+            Err(StoreError::WrongType { .. })
+        ));
+        assert_eq!(store.smembers("out"), Ok(members(&["2"])));
+    }
+}
@@ -0,0 +1,294 @@
+use crate::reactive_store::wal::{crc32, Clock};
+use crate::reactive_store::{
+    DecodeError, Entry, Persist, ReactiveStore, StoreConfig, StoreData, StoreKey,
+};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use thiserror::Error;
+
+/// Identifies a store snapshot.
+const MAGIC: &[u8; 6] = b"rrsnap";
+
+/// Version of the image format, bumped on every incompatible change.
+const VERSION: u16 = 1;
+
+/// Magic, version and the CRC-32 of everything after the header.
+const HEADER: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{} is not a store snapshot", path.display())]
+    NotASnapshot { path: PathBuf },
+    #[error("snapshot format version {version} is not supported")]
+    UnsupportedVersion { version: u16 },
+    #[error("snapshot checksum does not match its contents")]
+    Corrupt,
+    /// The snapshot is intact but could not be decoded, for example because
+    /// it was written for another key or value type.
+    #[error("snapshot is malformed: {0}")]
+    Malformed(#[from] DecodeError),
+}
+
+/// A key as saved in an image, with its deadline in wall clock milliseconds.
+#[derive(Debug, Clone)]
+pub(crate) struct Saved<K, V> {
+    key: K,
+    value: V,
+    revision: u64,
+    expires_at: Option<u64>,
+}
+
+impl<K: Persist, V: Persist> Persist for Saved<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.key.encode(out);
+        self.value.encode(out);
+        self.revision.encode(out);
+        self.expires_at.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Saved {
+            key: K::decode(input)?,
+            value: V::decode(input)?,
+            revision: u64::decode(input)?,
+            expires_at: Persist::decode(input)?,
+        })
+    }
+}
+
+/// Rebuilds the keyspace saved in an image.
+pub(crate) fn load<K: StoreKey, V>(
+    entries: Vec<Saved<K, V>>,
+    clock: Clock,
+) -> HashMap<K, Entry<V>> {
+    entries
+        .into_iter()
+        .map(|saved| {
+            let entry = Entry {
+                value: saved.value,
+                expires_at: saved.expires_at.map(|at| clock.instant(at)),
+                revision: saved.revision,
+            };
+            (saved.key, entry)
+        })
+        .collect()
+}
+
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    /**
+     * Copies every live key and the revision it was current at. Only the
+     * copy happens under the read lock, so writers wait for a clone of the
+     * keyspace but never for disk.
+     */
+    pub(crate) fn image(&self) -> (u64, Vec<Saved<K, V>>) {
+        self.image_locked(&self.data.read().unwrap())
+    }
+
+    /// Like [`ReactiveStore::image`], for a caller already holding a lock.
+    pub(crate) fn image_locked(&self, data: &HashMap<K, Entry<V>>) -> (u64, Vec<Saved<K, V>>) {
+        let now = Instant::now();
+        let clock = Clock::now();
+        let entries = data
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, entry)| Saved {
+                key: key.clone(),
+                value: entry.value.clone(),
+                revision: entry.revision,
+                expires_at: entry.expires_at.map(|at| clock.wall_clock(at)),
+            })
+            .collect();
+        (self.events.revision(), entries)
+    }
+}
+
+/**
+ * Point-in-time copies of the whole store. A snapshot file is the magic
+ * `rrsnap`, the format version as a little endian `u16`, the CRC-32 of the
+ * rest of the file, then the revision it was taken at and every key with
+ * its value, revision and deadline. The same image is what a log starts
+ * with once it has been rewritten.
+ */
+impl<K, V> ReactiveStore<K, V>
+where
+    K: StoreKey + Persist,
+    V: StoreData + Persist,
+{
+    /**
+     * Writes every key, value and TTL deadline to `path`, returning the
+     * revision the snapshot reflects. The file is written next to `path`
+     * and renamed over it once synced, so an existing snapshot is only
+     * ever replaced by a complete one. The directory is synced after the
+     * rename, so that the new snapshot survives a crash once this returns.
+     */
+    pub fn snapshot_to(&self, path: impl AsRef<Path>) -> Result<u64, SnapshotError> {
+        let (revision, entries) = self.image();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        VERSION.encode(&mut bytes);
+        bytes.extend_from_slice(&[0; 4]);
+        revision.encode(&mut bytes);
+        entries.encode(&mut bytes);
+        let checksum = crc32(&bytes[HEADER..]);
+        bytes[8..HEADER].copy_from_slice(&checksum.to_le_bytes());
+
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".tmp");
+        let mut file = File::create(&partial)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&partial, path)?;
+        sync_parent(path)?;
+        Ok(revision)
+    }
+
+    /**
+     * Creates a store holding the keys saved at `path` by
+     * [`ReactiveStore::snapshot_to`]. Keys keep their remaining TTL and
+     * revisions continue from the snapshot.
+     */
+    pub fn restore_from(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        Self::restore_with_config(path, StoreConfig::default())
+    }
+
+    /// Like [`ReactiveStore::restore_from`], with the given configuration.
+    pub fn restore_with_config(
+        path: impl AsRef<Path>,
+        config: StoreConfig,
+    ) -> Result<Self, SnapshotError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        let Some(header) = bytes.get(..HEADER).filter(|bytes| bytes.starts_with(MAGIC)) else {
+            return Err(SnapshotError::NotASnapshot {
+                path: path.to_path_buf(),
+            });
+        };
+        let version = u16::from_le_bytes(header[6..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion { version });
+        }
+        let checksum = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if crc32(&bytes[HEADER..]) != checksum {
+            return Err(SnapshotError::Corrupt);
+        }
+
+        let input = &mut &bytes[HEADER..];
+        let revision = u64::decode(input)?;
+        let entries = Vec::decode(input)?;
+        if !input.is_empty() {
+            return Err(DecodeError::TrailingBytes { len: input.len() }.into());
+        }
+        let data = load(entries, Clock::now());
+        Ok(Self::with_parts(config, data, revision, None))
+    }
+}
+
+/// Syncs the directory holding `path`, which makes a rename into it durable.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+/// Directories cannot be opened to sync them outside Unix.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::{StoreValue, Ttl};
+    use std::time::Duration;
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("r-reactive-{}-{name}.snap", std::process::id()))
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let path = snapshot_path("round-trip");
+        let store = ReactiveStore::new();
+        store.set("text", "hello".into()).unwrap();
+        store
+            .set_with_ttl("session", "token".into(), Duration::from_secs(60))
+            .unwrap();
+        store
+            .set_with_ttl("gone", "soon".into(), Duration::from_millis(1))
+            .unwrap();
+        store.sadd("tags", &["a", "b"]).unwrap();
+        store.incr_by("visits", 3).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+
+        let revision = store.snapshot_to(&path).unwrap();
+        assert_eq!(revision, store.revision());
+        // Later writes are not part of the snapshot
+        store.set("later", StoreValue::Null).unwrap();
+
+        let restored = ReactiveStore::<String, StoreValue>::restore_from(&path).unwrap();
+        for key in ["text", "tags", "visits"] {
+            assert_eq!(restored.get(key), store.get(key), "{key}");
+            assert_eq!(restored.get_versioned(key), store.get_versioned(key));
+        }
+        assert!(restored.get("later").is_none());
+        assert!(restored.get("gone").is_none());
+        assert!(matches!(
+            restored.ttl("session"),
+            Some(Ttl::Remaining(left)) if left > Duration::from_secs(55)
+        ));
+        assert_eq!(restored.revision(), revision);
+        restored.set("next", StoreValue::Null).unwrap();
+        assert_eq!(restored.revision(), revision + 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_damaged_snapshot_is_refused() {
+        let path = snapshot_path("damaged");
+        let store = ReactiveStore::new();
+        store.set("a", 1.into()).unwrap();
+        store.snapshot_to(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let restore = || ReactiveStore::<String, StoreValue>::restore_from(&path);
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        fs::write(&path, &flipped).unwrap();
+        assert!(matches!(restore(), Err(SnapshotError::Corrupt)));
+
+        let mut newer = bytes.clone();
+        newer[6..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        fs::write(&path, &newer).unwrap();
+        assert!(matches!(
+            restore(),
+            Err(SnapshotError::UnsupportedVersion { version }) if version == VERSION + 1
+        ));
+
+        // Bytes past the image are refused even with a matching checksum
+        let mut longer = bytes.clone();
+        longer.push(0);
+        let checksum = crc32(&longer[HEADER..]);
+        longer[8..HEADER].copy_from_slice(&checksum.to_le_bytes());
+        fs::write(&path, &longer).unwrap();
+        assert!(matches!(
+            restore(),
+            Err(SnapshotError::Malformed(DecodeError::TrailingBytes {
+                len: 1
+            }))
+        ));
+
+        fs::write(&path, b"rrlog").unwrap();
+        assert!(matches!(restore(), Err(SnapshotError::NotASnapshot { .. })));
+        fs::remove_file(&path).unwrap();
+    }
+}
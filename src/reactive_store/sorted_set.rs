@@ -0,0 +1,445 @@
+use crate::reactive_store::list::resolve_range;
+use crate::reactive_store::{Edit, ReactiveStore, SortedSetEdit, StoreError, StoreValue};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::{Bound, RangeBounds};
+
+/// A score ordered by `f64::total_cmp`. Scores are never NaN.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/**
+ * Members ordered by score, ties broken by member. Inserts, score updates
+ * and removals are O(log n); finding the rank of a member walks the members
+ * ranked before it.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<String, f64>,
+    order: BTreeSet<(Score, String)>,
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        // The order is derived from the scores, compared the way it sorts them
+        self.len() == other.len()
+            && self.scores.iter().all(|(member, score)| {
+                other.scores.get(member).map(|other| Score(*other)) == Some(Score(*score))
+            })
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /**
+     * Sets the score of `member` and returns its previous score.
+     *
+     * Panics if `score` is NaN.
+     */
+    pub fn insert(&mut self, member: &str, score: f64) -> Option<f64> {
+        assert!(!score.is_nan(), "sorted set scores must not be NaN");
+        let old = self.scores.insert(member.to_string(), score);
+        if let Some(old) = old {
+            self.order.remove(&(Score(old), member.to_string()));
+        }
+        self.order.insert((Score(score), member.to_string()));
+        old
+    }
+
+    /// Removes `member` and returns its score.
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let (member, score) = self.scores.remove_entry(member)?;
+        self.order.remove(&(Score(score), member));
+        Some(score)
+    }
+
+    /// Position of `member` counting from the lowest score, starting at 0.
+    pub fn rank(&self, member: &str) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(
+            self.order
+                .range(..(Score(score), member.to_string()))
+                .count(),
+        )
+    }
+
+    /// Every member with its score, lowest score first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&str, f64)> {
+        self.order
+            .iter()
+            .map(|(score, member)| (member.as_str(), score.0))
+    }
+
+    /// Members whose score lies within `scores`, lowest score first.
+    pub fn range_by_score<R>(&self, scores: R) -> impl Iterator<Item = (&str, f64)>
+    where
+        R: RangeBounds<f64>,
+    {
+        let start = scores.start_bound().cloned();
+        let end = scores.end_bound().cloned();
+        let from = match start {
+            Bound::Included(min) | Bound::Excluded(min) => {
+                Bound::Included((Score(min), String::new()))
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        // Bounds compare the way the index orders scores, so -0.0 ranks before 0.0
+        self.order
+            .range((from, Bound::Unbounded))
+            .skip_while(
+                move |(score, _)| matches!(start, Bound::Excluded(min) if *score == Score(min)),
+            )
+            .take_while(move |(score, _)| match end {
+                Bound::Included(max) => *score <= Score(max),
+                Bound::Excluded(max) => *score < Score(max),
+                Bound::Unbounded => true,
+            })
+            .map(|(score, member)| (member.as_str(), score.0))
+    }
+
+    pub fn pop_min(&mut self) -> Option<(String, f64)> {
+        let (score, member) = self.order.pop_first()?;
+        self.scores.remove(&member);
+        Some((member, score.0))
+    }
+
+    pub fn pop_max(&mut self) -> Option<(String, f64)> {
+        let (score, member) = self.order.pop_last()?;
+        self.scores.remove(&member);
+        Some((member, score.0))
+    }
+}
+
+/**
+ * Sorted set commands. Writes publish a [`SortedSetEdit`] with only the
+ * members whose score changed. A missing key behaves as an empty sorted set.
+ */
+impl ReactiveStore {
+    /**
+     * Sets the score of each of `members`, adding the ones that are missing,
+     * and returns how many were added. Fails without changing anything if
+     * any score is NaN.
+     */
+    pub fn zadd(&self, key: &str, members: &[(&str, f64)]) -> Result<usize, StoreError> {
+        if members.iter().any(|(_, score)| score.is_nan()) {
+            return Err(invalid_score(key));
+        }
+        self.edit_sorted_set(key, true, |set| {
+            let mut added = 0;
+            let mut scored = Vec::new();
+            for (member, score) in members {
+                match set.insert(member, *score) {
+                    None => added += 1,
+                    // As the order sees it, so that -0.0 and 0.0 differ
+                    Some(old) if Score(old) == Score(*score) => continue,
+                    Some(_) => {}
+                }
+                scored.push((member.to_string(), *score));
+            }
+            Ok((added, SortedSetEdit::Scored { members: scored }))
+        })
+    }
+
+    /// Adds `delta` to the score of `member`, starting from 0, and returns the new score.
+    pub fn zincrby(&self, key: &str, member: &str, delta: f64) -> Result<f64, StoreError> {
+        self.edit_sorted_set(key, true, |set| {
+            let score = set.score(member).unwrap_or(0.0) + delta;
+            if score.is_nan() {
+                return Err(invalid_score(key));
+            }
+            set.insert(member, score);
+            let edit = SortedSetEdit::Scored {
+                members: vec![(member.to_string(), score)],
+            };
+            Ok((score, edit))
+        })
+    }
+
+    /// Removes `members` and returns how many of them were present.
+    pub fn zrem(&self, key: &str, members: &[&str]) -> Result<usize, StoreError> {
+        self.edit_sorted_set(key, false, |set| {
+            let removed: Vec<String> = members
+                .iter()
+                .filter(|member| set.remove(member).is_some())
+                .map(|member| member.to_string())
+                .collect();
+            Ok((removed.len(), SortedSetEdit::Removed { members: removed }))
+        })
+    }
+
+    pub fn zscore(&self, key: &str, member: &str) -> Result<Option<f64>, StoreError> {
+        Ok(self
+            .read_sorted_set(key, |set| set.score(member))?
+            .flatten())
+    }
+
+    /// Number of members, 0 if the key does not exist.
+    pub fn zcard(&self, key: &str) -> Result<usize, StoreError> {
+        Ok(self.read_sorted_set(key, SortedSet::len)?.unwrap_or(0))
+    }
+
+    /// Rank of `member` counting from the lowest score, starting at 0.
+    pub fn zrank(&self, key: &str, member: &str) -> Result<Option<usize>, StoreError> {
+        Ok(self.read_sorted_set(key, |set| set.rank(member))?.flatten())
+    }
+
+    /// Rank of `member` counting from the highest score, starting at 0.
+    pub fn zrevrank(&self, key: &str, member: &str) -> Result<Option<usize>, StoreError> {
+        let rank =
+            self.read_sorted_set(key, |set| set.rank(member).map(|rank| set.len() - 1 - rank))?;
+        Ok(rank.flatten())
+    }
+
+    /**
+     * Members ranked from `start` to `stop`, both inclusive, lowest score
+     * first. Negative ranks count from the highest score.
+     */
+    pub fn zrange_by_rank(
+        &self,
+        key: &str,
+        start: isize,
+        stop: isize,
+    ) -> Result<Vec<(String, f64)>, StoreError> {
+        let range = self.read_sorted_set(key, |set| {
+            let range = resolve_range(start, stop, set.len());
+            set.iter()
+                .skip(range.start)
+                .take(range.len())
+                .map(|(member, score)| (member.to_string(), score))
+                .collect()
+        })?;
+        Ok(range.unwrap_or_default())
+    }
+
+    /// Members whose score lies within `scores`, lowest score first.
+    pub fn zrange_by_score<R>(&self, key: &str, scores: R) -> Result<Vec<(String, f64)>, StoreError>
+    where
+        R: RangeBounds<f64>,
+    {
+        let range = self.read_sorted_set(key, |set| {
+            set.range_by_score(scores)
+                .map(|(member, score)| (member.to_string(), score))
+                .collect()
+        })?;
+        Ok(range.unwrap_or_default())
+    }
+
+    /// Removes and returns the member with the lowest score.
+    pub fn zpop_min(&self, key: &str) -> Result<Option<(String, f64)>, StoreError> {
+        self.zpop(key, SortedSet::pop_min)
+    }
+
+    /// Removes and returns the member with the highest score.
+    pub fn zpop_max(&self, key: &str) -> Result<Option<(String, f64)>, StoreError> {
+        self.zpop(key, SortedSet::pop_max)
+    }
+
+    fn zpop(
+        &self,
+        key: &str,
+        pop: fn(&mut SortedSet) -> Option<(String, f64)>,
+    ) -> Result<Option<(String, f64)>, StoreError> {
+        self.edit_sorted_set(key, false, |set| {
+            let popped = pop(set);
+            let members = popped.iter().map(|(member, _)| member.clone()).collect();
+            Ok((popped, SortedSetEdit::Removed { members }))
+        })
+    }
+
+    /**
+     * Runs `edit` on the sorted set at `key`, creating an empty one first if
+     * `create` is set. An edit that changes no member is not published.
+     */
+    fn edit_sorted_set<T, F>(&self, key: &str, create: bool, edit: F) -> Result<T, StoreError>
+    where
+        T: Default,
+        F: FnOnce(&mut SortedSet) -> Result<(T, SortedSetEdit), StoreError>,
+    {
+        let create = create.then(|| StoreValue::SortedSet(SortedSet::new()));
+        self.edit_value(key, create, |value| match value {
+            None => Ok((T::default(), None)),
+            Some(StoreValue::SortedSet(set)) => {
+                let (output, edit) = edit(set)?;
+                let changed = match &edit {
+                    SortedSetEdit::Scored { members } => !members.is_empty(),
+                    SortedSetEdit::Removed { members } => !members.is_empty(),
+                };
+                Ok((output, changed.then_some(Edit::SortedSet(edit))))
+            }
+            Some(other) => Err(StoreError::wrong_type(key, "sorted set", other)),
+        })
+    }
+
+    fn read_sorted_set<T, F>(&self, key: &str, read: F) -> Result<Option<T>, StoreError>
+    where
+        F: FnOnce(&SortedSet) -> T,
+    {
+        self.read_value(key, |value| match value {
+            StoreValue::SortedSet(set) => Ok(read(set)),
+            other => Err(StoreError::wrong_type(key, "sorted set", other)),
+        })
+        .transpose()
+    }
+}
+
+fn invalid_score(key: &str) -> StoreError {
+    StoreError::InvalidScore {
+        key: key.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::ChangeEvent;
+
+    fn ranked(members: &[(&str, f64)]) -> Vec<(String, f64)> {
+        members
+            .iter()
+            .map(|(member, score)| (member.to_string(), *score))
+            .collect()
+    }
+
+    #[test]
+    fn test_sorted_set_orders_by_score() {
+        let mut set = SortedSet::new();
+        set.insert("c", 3.0);
+        set.insert("a", 1.0);
+        set.insert("b", 1.0);
+        assert_eq!(set.insert("c", 0.5), Some(3.0));
+
+        let order: Vec<_> = set.iter().map(|(member, _)| member).collect();
+        assert_eq!(order, vec!["c", "a", "b"]);
+        assert_eq!(set.rank("b"), Some(2));
+        let in_range: Vec<_> = set.range_by_score(0.5..1.0).collect();
+        assert_eq!(in_range, vec![("c", 0.5)]);
+        let exclusive: Vec<_> = set
+            .range_by_score((Bound::Excluded(0.5), Bound::Unbounded))
+            .map(|(member, _)| member)
+            .collect();
+        assert_eq!(exclusive, vec!["a", "b"]);
+
+        let mut signs = SortedSet::new();
+        signs.insert("negative", -0.0);
+        signs.insert("positive", 0.0);
+        let from_zero: Vec<_> = signs
+            .range_by_score(0.0..)
+            .map(|(member, _)| member)
+            .collect();
+        assert_eq!(from_zero, vec!["positive"]);
+        let below_zero: Vec<_> = signs
+            .range_by_score(..0.0)
+            .map(|(member, _)| member)
+            .collect();
+        assert_eq!(below_zero, vec!["negative"]);
+
+        assert_eq!(set.pop_max(), Some(("b".into(), 1.0)));
+        assert_eq!(set.pop_min(), Some(("c".into(), 0.5)));
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_sorted_set_commands() {
+        let store = ReactiveStore::new();
+        let mut sub = store.subscribe();
+
+        assert_eq!(
+            store.zadd("board", &[("ann", 10.0), ("bob", 20.0), ("cat", 15.0)]),
+            Ok(3)
+        );
+        assert_eq!(store.zadd("board", &[("ann", 10.0)]), Ok(0));
+        assert_eq!(store.zincrby("board", "ann", 15.0), Ok(25.0));
+        assert_eq!(store.zrank("board", "ann"), Ok(Some(2)));
+        assert_eq!(store.zrevrank("board", "ann"), Ok(Some(0)));
+        assert_eq!(
+            store.zrange_by_rank("board", 0, 1),
+            Ok(ranked(&[("cat", 15.0), ("bob", 20.0)]))
+        );
+        assert_eq!(
+            store.zrange_by_score("board", 16.0..),
+            Ok(ranked(&[("bob", 20.0), ("ann", 25.0)]))
+        );
+        assert_eq!(store.zpop_max("board"), Ok(Some(("ann".into(), 25.0))));
+        assert_eq!(store.zrem("board", &["cat", "dan"]), Ok(1));
+        assert_eq!(store.zcard("board"), Ok(1));
+        assert_eq!(store.zpop_min("missing"), Ok(None));
+
+        // The unchanged score of "ann" was not published
+        let mut copy = StoreValue::SortedSet(SortedSet::new());
+        let mut events = 0;
+        while let Ok(ChangeEvent::Edited { edit, .. }) = sub.try_recv() {
+            edit.apply(&mut copy);
+            events += 1;
+        }
+        assert_eq!(events, 4);
+        assert_eq!(Some(copy), store.get("board"));
+
+        // -0.0 ranks before 0.0, so the rescore is a change like any other
+        store.zadd("signs", &[("a", 0.0), ("b", 0.0)]).unwrap();
+        assert_eq!(store.zadd("signs", &[("b", -0.0)]), Ok(0));
+        assert_eq!(store.zrank("signs", "b"), Ok(Some(0)));
+        let mut copy = StoreValue::SortedSet(SortedSet::new());
+        for _ in 0..2 {
+            let Ok(ChangeEvent::Edited { edit, .. }) = sub.try_recv() else {
+                panic!("expected both writes to be published");
+            };
+            edit.apply(&mut copy);
+        }
+        assert_eq!(Some(copy), store.get("signs"));
+    }
+
+    #[test]
+    fn test_sorted_set_rejects_nan() {
+        let store = ReactiveStore::new();
+        store.zadd("board", &[("ann", f64::INFINITY)]).unwrap();
+
+        assert_eq!(
+            store.zadd("board", &[("bob", 1.0), ("cat", f64::NAN)]),
+            Err(StoreError::InvalidScore {
+                key: "board".into()
+            })
+        );
+        assert_eq!(
+            store.zincrby("board", "ann", f64::NEG_INFINITY),
+            Err(StoreError::InvalidScore {
+                key: "board".into()
+            })
+        );
+        assert_eq!(store.zcard("board"), Ok(1));
+    }
+}
@@ -0,0 +1,426 @@
+use crate::reactive_store::{Change, ChangeEvent, KeyFilter, StoreKey};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+/**
+ * Combinators for streams of change events, available on every
+ * [`Stream`] such as a [`Subscription`](crate::reactive_store::Subscription).
+ * The adapters are streams themselves, so they mix freely with
+ * `StreamExt`, `tokio-stream` and the rest of the async ecosystem.
+ *
+ * Adapters require their inner stream to be `Unpin`, which every type in
+ * this module is.
+ */
+pub trait EventStream: Stream {
+    /// Keeps events about keys matching `filter`, plus store-wide events,
+    /// see [`ChangeEvent::matches`].
+    fn filter_key<K, V>(self, filter: KeyFilter<K>) -> FilterKey<Self, K>
+    where
+        Self: Stream<Item = ChangeEvent<K, V>> + Sized,
+        K: StoreKey,
+    {
+        FilterKey {
+            stream: self,
+            filter,
+        }
+    }
+
+    /**
+     * Turns events into `(key, value)` pairs, mapping the new value with `f`.
+     * Removals and expiries yield `None`, and a transaction one pair per key
+     * it changed. Events that do not carry the new value (`Edited`,
+     * `TtlChanged`, `Cleared`) are skipped.
+     */
+    fn map_value<K, V, F, T>(self, f: F) -> MapValue<Self, F, K, T>
+    where
+        Self: Stream<Item = ChangeEvent<K, V>> + Sized,
+        F: FnMut(&V) -> T,
+    {
+        MapValue {
+            stream: self,
+            f,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Drops writes that stored the value the key already had.
+    fn distinct_until_changed<K, V>(self) -> DistinctUntilChanged<Self>
+    where
+        Self: Stream<Item = ChangeEvent<K, V>> + Sized,
+        V: PartialEq,
+    {
+        DistinctUntilChanged { stream: self }
+    }
+
+    /// Emits only the latest item once no new item arrived for `quiet`.
+    fn debounce(self, quiet: Duration) -> Debounce<Self>
+    where
+        Self: Sized,
+    {
+        Debounce {
+            stream: self,
+            quiet,
+            pending: None,
+            sleep: None,
+            done: false,
+        }
+    }
+
+    /// Emits an item, then drops everything arriving within `interval` of it.
+    fn throttle(self, interval: Duration) -> Throttle<Self>
+    where
+        Self: Sized,
+    {
+        Throttle {
+            stream: self,
+            interval,
+            open_at: None,
+        }
+    }
+
+    /**
+     * Groups items into batches of at most `max` items. A partial batch is
+     * emitted once `window` has passed since its first item.
+     */
+    fn batch(self, max: usize, window: Duration) -> Batch<Self>
+    where
+        Self: Sized,
+    {
+        Batch {
+            stream: self,
+            max: max.max(1),
+            window,
+            items: Vec::new(),
+            sleep: None,
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream + ?Sized> EventStream for S {}
+
+#[derive(Debug)]
+pub struct FilterKey<S, K = String> {
+    stream: S,
+    filter: KeyFilter<K>,
+}
+
+impl<S, K, V> Stream for FilterKey<S, K>
+where
+    S: Stream<Item = ChangeEvent<K, V>> + Unpin,
+    K: StoreKey + Unpin,
+{
+    type Item = ChangeEvent<K, V>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ChangeEvent<K, V>>> {
+        loop {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(event)) => {
+                    if event.matches(&self.filter) {
+                        return Poll::Ready(Some(event));
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MapValue<S, F, K = String, T = crate::reactive_store::StoreValue> {
+    stream: S,
+    f: F,
+    /// Pairs from a transaction not handed out yet.
+    ready: VecDeque<(K, Option<T>)>,
+}
+
+impl<S, F, K, V, T> Stream for MapValue<S, F, K, T>
+where
+    S: Stream<Item = ChangeEvent<K, V>> + Unpin,
+    F: FnMut(&V) -> T + Unpin,
+    K: Unpin,
+    T: Unpin,
+{
+    type Item = (K, Option<T>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(mapped) = self.ready.pop_front() {
+                return Poll::Ready(Some(mapped));
+            }
+            let event = match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(event)) => event,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            let mapped = match event {
+                ChangeEvent::Set { key, new, .. } => (key, Some((self.f)(&new))),
+                ChangeEvent::Removed { key, .. } | ChangeEvent::Expired { key, .. } => (key, None),
+                ChangeEvent::Committed { changes, .. } => {
+                    let this = &mut *self;
+                    this.ready
+                        .extend(changes.into_iter().map(|change| match change {
+                            Change::Set { key, new, .. } => (key, Some((this.f)(&new))),
+                            Change::Removed { key, .. } => (key, None),
+                        }));
+                    continue;
+                }
+                ChangeEvent::Edited { .. }
+                | ChangeEvent::TtlChanged { .. }
+                | ChangeEvent::Cleared { .. } => continue,
+            };
+            return Poll::Ready(Some(mapped));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DistinctUntilChanged<S> {
+    stream: S,
+}
+
+impl<S, K, V> Stream for DistinctUntilChanged<S>
+where
+    S: Stream<Item = ChangeEvent<K, V>> + Unpin,
+    V: PartialEq,
+{
+    type Item = ChangeEvent<K, V>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ChangeEvent<K, V>>> {
+        loop {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(ChangeEvent::Set {
+                    old: Some(old),
+                    new,
+                    ..
+                })) if old == new => continue,
+                other => return other,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Debounce<S: Stream> {
+    stream: S,
+    quiet: Duration,
+    pending: Option<S::Item>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    done: bool,
+}
+
+impl<S> Stream for Debounce<S>
+where
+    S: Stream + Unpin,
+    S::Item: Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        while !this.done {
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.pending = Some(item);
+                    let deadline = Instant::now() + this.quiet;
+                    match &mut this.sleep {
+                        Some(sleep) => sleep.as_mut().reset(deadline),
+                        None => this.sleep = Some(Box::pin(tokio::time::sleep_until(deadline))),
+                    }
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        if this.done {
+            // Flush whatever was waiting for quiet time before ending
+            return Poll::Ready(this.pending.take());
+        }
+        let quiet = match (&this.pending, &mut this.sleep) {
+            (Some(_), Some(sleep)) => sleep.as_mut().poll(cx).is_ready(),
+            _ => false,
+        };
+        if quiet {
+            return Poll::Ready(this.pending.take());
+        }
+        Poll::Pending
+    }
+}
+
+#[derive(Debug)]
+pub struct Throttle<S> {
+    stream: S,
+    interval: Duration,
+    open_at: Option<Instant>,
+}
+
+impl<S> Stream for Throttle<S>
+where
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        loop {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let now = Instant::now();
+                    if self.open_at.is_some_and(|open_at| now < open_at) {
+                        continue;
+                    }
+                    self.open_at = Some(now + self.interval);
+                    return Poll::Ready(Some(item));
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Batch<S: Stream> {
+    stream: S,
+    max: usize,
+    window: Duration,
+    items: Vec<S::Item>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    done: bool,
+}
+
+impl<S> Stream for Batch<S>
+where
+    S: Stream + Unpin,
+    S::Item: Unpin,
+{
+    type Item = Vec<S::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<S::Item>>> {
+        let this = &mut *self;
+        while !this.done {
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if this.items.is_empty() {
+                        this.sleep = Some(Box::pin(tokio::time::sleep(this.window)));
+                    }
+                    this.items.push(item);
+                    if this.items.len() >= this.max {
+                        this.sleep = None;
+                        return Poll::Ready(Some(mem::take(&mut this.items)));
+                    }
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        if this.done {
+            if this.items.is_empty() {
+                return Poll::Ready(None);
+            }
+            return Poll::Ready(Some(mem::take(&mut this.items)));
+        }
+        let elapsed = match &mut this.sleep {
+            Some(sleep) => sleep.as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+        if elapsed {
+            this.sleep = None;
+            return Poll::Ready(Some(mem::take(&mut this.items)));
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::{ReactiveStore, StoreValue};
+    use futures_util::{FutureExt, StreamExt};
+
+    #[tokio::test]
+    async fn test_filter_map_and_distinct() {
+        let store = ReactiveStore::new();
+        let mut totals = store
+            .subscribe()
+            .filter_key(KeyFilter::Prefix("cart:".into()))
+            .distinct_until_changed()
+            .map_value(|value| match value {
+                StoreValue::Counter(n) => *n,
+                _ => 0,
+            });
+
+        store.set("cart:1", StoreValue::Counter(10)).unwrap();
+        store.set("other", StoreValue::Counter(1)).unwrap();
+        store.set("cart:1", StoreValue::Counter(10)).unwrap();
+        store.set("cart:1", StoreValue::Counter(12)).unwrap();
+        store.remove("cart:1").unwrap();
+
+        assert_eq!(totals.next().await, Some(("cart:1".to_string(), Some(10))));
+        assert_eq!(totals.next().await, Some(("cart:1".to_string(), Some(12))));
+        assert_eq!(totals.next().await, Some(("cart:1".to_string(), None)));
+    }
+
+    #[tokio::test]
+    async fn test_batch_by_size_and_window() {
+        let store = ReactiveStore::new();
+        let mut batches = store.subscribe().batch(3, Duration::from_millis(30));
+
+        for i in 0..4 {
+            store.set("key", StoreValue::Counter(i)).unwrap();
+        }
+
+        let revisions = |batch: Vec<ChangeEvent>| -> Vec<u64> {
+            batch.iter().map(ChangeEvent::revision).collect()
+        };
+        assert_eq!(revisions(batches.next().await.unwrap()), vec![1, 2, 3]);
+        // The last event only arrives once the window has passed
+        assert_eq!(revisions(batches.next().await.unwrap()), vec![4]);
+    }
+
+    #[tokio::test]
+    async fn test_debounce_and_throttle() {
+        let store = ReactiveStore::new();
+        let mut debounced = store.subscribe().debounce(Duration::from_millis(30));
+        let mut throttled = store.subscribe().throttle(Duration::from_secs(60));
+
+        for i in 0..5 {
+            store.set("key", StoreValue::Counter(i)).unwrap();
+        }
+        assert_eq!(debounced.next().await.unwrap().revision(), 5);
+        assert_eq!(throttled.next().await.unwrap().revision(), 1);
+
+        drop(store);
+        assert_eq!(throttled.next().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_follows_tokio_time() {
+        let store = ReactiveStore::new();
+        let mut revisions = store
+            .subscribe()
+            .throttle(Duration::from_secs(60))
+            .map(|event| event.revision());
+
+        store.set("key", StoreValue::Counter(0)).unwrap();
+        store.set("key", StoreValue::Counter(1)).unwrap();
+        assert_eq!(revisions.next().await, Some(1));
+        assert_eq!(revisions.next().now_or_never(), None);
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        store.set("key", StoreValue::Counter(2)).unwrap();
+        assert_eq!(revisions.next().await, Some(3));
+    }
+}
@@ -0,0 +1,559 @@
+use crate::reactive_store::{
+    DecodeError, Edit, Persist, ReactiveStore, RecvError, StoreError, StoreValue, StreamEdit,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * Identifies a stream entry: the wall clock time it was added at in
+ * milliseconds, and a sequence number for entries added within the same
+ * millisecond. IDs only ever grow within a stream.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    /// Lower than any ID a stream hands out.
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamEntry {
+    pub id: StreamId,
+    pub fields: HashMap<String, StoreValue>,
+}
+
+/// An entry delivered to a consumer group but not acknowledged yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingEntry {
+    pub id: StreamId,
+    /// The consumer the entry was last delivered to.
+    pub consumer: String,
+    pub deliveries: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct ConsumerGroup {
+    last_delivered: StreamId,
+    pending: BTreeMap<StreamId, PendingEntry>,
+}
+
+/// How [`ReactiveStore::xtrim`] shortens a stream, always from the oldest entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamTrim {
+    /// Keep at most this many entries.
+    MaxLen(usize),
+    /// Drop entries whose ID is older than this.
+    MaxAge(Duration),
+}
+
+/**
+ * An append-only log of entries ordered by ID, each a map of fields, with
+ * the consumer groups reading from it.
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stream {
+    entries: BTreeMap<StreamId, HashMap<String, StoreValue>>,
+    last_id: StreamId,
+    groups: HashMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The ID of the latest entry ever added, even if it was trimmed since.
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// The entries whose ID lies within `ids`, oldest first.
+    pub fn range<R>(&self, ids: R) -> impl Iterator<Item = StreamEntry> + '_
+    where
+        R: RangeBounds<StreamId>,
+    {
+        self.entries.range(ids).map(|(id, fields)| StreamEntry {
+            id: *id,
+            fields: fields.clone(),
+        })
+    }
+
+    /// The entries `group` has delivered and not had acknowledged, oldest first.
+    pub fn pending(&self, group: &str) -> Option<Vec<PendingEntry>> {
+        let group = self.groups.get(group)?;
+        Some(group.pending.values().cloned().collect())
+    }
+
+    fn next_id(&self) -> StreamId {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        if now > self.last_id.ms {
+            StreamId::new(now, 0)
+        } else {
+            StreamId::new(self.last_id.ms, self.last_id.seq + 1)
+        }
+    }
+
+    pub(crate) fn append(&mut self, id: StreamId, fields: HashMap<String, StoreValue>) {
+        self.entries.insert(id, fields);
+        self.last_id = self.last_id.max(id);
+    }
+
+    pub(crate) fn trim_front(&mut self, removed: usize) {
+        for _ in 0..removed {
+            self.entries.pop_first();
+        }
+    }
+
+    pub(crate) fn create_group(&mut self, group: &str, start: StreamId) -> bool {
+        if self.groups.contains_key(group) {
+            return false;
+        }
+        let group = group.to_string();
+        self.groups.insert(
+            group,
+            ConsumerGroup {
+                last_delivered: start,
+                pending: BTreeMap::new(),
+            },
+        );
+        true
+    }
+
+    pub(crate) fn deliver(&mut self, group: &str, consumer: &str, ids: &[StreamId]) {
+        let Some(group) = self.groups.get_mut(group) else {
+            return;
+        };
+        for id in ids {
+            let pending = group.pending.entry(*id).or_insert_with(|| PendingEntry {
+                id: *id,
+                consumer: consumer.to_string(),
+                deliveries: 0,
+            });
+            pending.consumer = consumer.to_string();
+            pending.deliveries += 1;
+            group.last_delivered = group.last_delivered.max(*id);
+        }
+    }
+
+    pub(crate) fn ack(&mut self, group: &str, ids: &[StreamId]) -> usize {
+        let Some(group) = self.groups.get_mut(group) else {
+            return 0;
+        };
+        ids.iter()
+            .filter(|id| group.pending.remove(id).is_some())
+            .count()
+    }
+}
+
+/**
+ * Stream commands. Every write publishes a [`StreamEdit`]; appending an
+ * entry publishes the entry itself, so subscribers of the key see new
+ * entries as they arrive.
+ */
+impl ReactiveStore {
+    /// Appends an entry with an ID after every existing one, creating the stream if needed.
+    pub fn xadd(
+        &self,
+        key: &str,
+        fields: HashMap<String, StoreValue>,
+    ) -> Result<StreamId, StoreError> {
+        let output = self.edit_stream(key, true, |stream| {
+            let id = stream.next_id();
+            stream.append(id, fields.clone());
+            Ok((id, Some(StreamEdit::Added { id, fields })))
+        })?;
+        Ok(output.unwrap_or_default())
+    }
+
+    /// Number of entries, 0 if the key does not exist.
+    pub fn xlen(&self, key: &str) -> Result<usize, StoreError> {
+        Ok(self.read_stream(key, Stream::len)?.unwrap_or(0))
+    }
+
+    /// The entries whose ID lies within `ids`, oldest first.
+    pub fn xrange<R>(&self, key: &str, ids: R) -> Result<Vec<StreamEntry>, StoreError>
+    where
+        R: RangeBounds<StreamId>,
+    {
+        let entries = self.read_stream(key, |stream| stream.range(ids).collect())?;
+        Ok(entries.unwrap_or_default())
+    }
+
+    /**
+     * Returns up to `count` entries added after `after`, waiting for the
+     * stream to receive one if there are none yet. Gives up with an empty
+     * result once `timeout` has passed; `None` waits indefinitely.
+     */
+    pub async fn read_after(
+        &self,
+        key: &str,
+        after: StreamId,
+        count: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<StreamEntry>, StoreError> {
+        // Subscribing before the first read means no entry added in between is missed
+        let mut subscription = self.subscribe_key(key);
+        let wait = async {
+            loop {
+                let entries = self.xrange(key, (Bound::Excluded(after), Bound::Unbounded))?;
+                if !entries.is_empty() {
+                    return Ok(entries.into_iter().take(count).collect());
+                }
+                if let Err(RecvError::Closed) = subscription.recv().await {
+                    return Ok(Vec::new());
+                }
+            }
+        };
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, wait)
+                .await
+                .unwrap_or(Ok(Vec::new())),
+            None => wait.await,
+        }
+    }
+
+    /// Removes the oldest entries according to `trim` and returns how many were removed.
+    pub fn xtrim(&self, key: &str, trim: StreamTrim) -> Result<usize, StoreError> {
+        let output = self.edit_stream(key, false, |stream| {
+            let removed = match trim {
+                StreamTrim::MaxLen(len) => stream.len().saturating_sub(len),
+                StreamTrim::MaxAge(age) => {
+                    let cutoff = SystemTime::now()
+                        .checked_sub(age)
+                        .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+                        .map_or(0, |cutoff| cutoff.as_millis() as u64);
+                    stream.entries.range(..StreamId::new(cutoff, 0)).count()
+                }
+            };
+            if removed == 0 {
+                return Ok((0, None));
+            }
+            stream.trim_front(removed);
+            Ok((removed, Some(StreamEdit::Trimmed { removed })))
+        })?;
+        Ok(output.unwrap_or_default())
+    }
+
+    /**
+     * Creates consumer group `group`, which delivers the entries after
+     * `start`: `StreamId::MIN` for the whole stream, or the stream's last ID
+     * for new entries only. Creates the stream if needed. Returns false if
+     * the group already exists.
+     */
+    pub fn xgroup_create(
+        &self,
+        key: &str,
+        group: &str,
+        start: StreamId,
+    ) -> Result<bool, StoreError> {
+        let output = self.edit_stream(key, true, |stream| {
+            if !stream.create_group(group, start) {
+                return Ok((false, None));
+            }
+            let edit = StreamEdit::GroupCreated {
+                group: group.to_string(),
+                start,
+            };
+            Ok((true, Some(edit)))
+        })?;
+        Ok(output.unwrap_or_default())
+    }
+
+    /**
+     * Delivers up to `count` entries that `group` has not delivered to any
+     * consumer yet to `consumer`. They stay pending until acknowledged.
+     */
+    pub fn xreadgroup(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, StoreError> {
+        self.edit_stream(key, false, |stream| {
+            let Some(last_delivered) = stream.groups.get(group).map(|g| g.last_delivered) else {
+                return Err(no_such_group(key, group));
+            };
+            let entries: Vec<StreamEntry> = stream
+                .range((Bound::Excluded(last_delivered), Bound::Unbounded))
+                .take(count)
+                .collect();
+            if entries.is_empty() {
+                return Ok((entries, None));
+            }
+            let ids: Vec<StreamId> = entries.iter().map(|entry| entry.id).collect();
+            stream.deliver(group, consumer, &ids);
+            let edit = StreamEdit::Delivered {
+                group: group.to_string(),
+                consumer: consumer.to_string(),
+                ids,
+            };
+            Ok((entries, Some(edit)))
+        })?
+        .ok_or_else(|| no_such_group(key, group))
+    }
+
+    /// Acknowledges entries delivered by `group` and returns how many were pending.
+    pub fn xack(&self, key: &str, group: &str, ids: &[StreamId]) -> Result<usize, StoreError> {
+        let acked = self.edit_stream(key, false, |stream| {
+            if !stream.groups.contains_key(group) {
+                return Err(no_such_group(key, group));
+            }
+            let acked: Vec<StreamId> = ids
+                .iter()
+                .copied()
+                .filter(|id| stream.ack(group, &[*id]) == 1)
+                .collect();
+            if acked.is_empty() {
+                return Ok((0, None));
+            }
+            let edit = StreamEdit::Acked {
+                group: group.to_string(),
+                ids: acked.clone(),
+            };
+            Ok((acked.len(), Some(edit)))
+        })?;
+        Ok(acked.unwrap_or(0))
+    }
+
+    /// The entries `group` has delivered and not had acknowledged, oldest first.
+    pub fn xpending(&self, key: &str, group: &str) -> Result<Vec<PendingEntry>, StoreError> {
+        self.read_stream(key, |stream| stream.pending(group))?
+            .flatten()
+            .ok_or_else(|| no_such_group(key, group))
+    }
+
+    /**
+     * Runs `edit` on the stream at `key`, creating an empty one first if
+     * `create` is set. Returns `None` if the key does not exist.
+     */
+    fn edit_stream<T, F>(&self, key: &str, create: bool, edit: F) -> Result<Option<T>, StoreError>
+    where
+        F: FnOnce(&mut Stream) -> Result<(T, Option<StreamEdit>), StoreError>,
+    {
+        let create = create.then(|| StoreValue::Stream(Stream::new()));
+        self.edit_value(key, create, |value| match value {
+            None => Ok((None, None)),
+            Some(StoreValue::Stream(stream)) => {
+                let (output, edit) = edit(stream)?;
+                Ok((Some(output), edit.map(Edit::Stream)))
+            }
+            Some(other) => Err(StoreError::wrong_type(key, "stream", other)),
+        })
+    }
+
+    fn read_stream<T, F>(&self, key: &str, read: F) -> Result<Option<T>, StoreError>
+    where
+        F: FnOnce(&Stream) -> T,
+    {
+        self.read_value(key, |value| match value {
+            StoreValue::Stream(stream) => Ok(read(stream)),
+            other => Err(StoreError::wrong_type(key, "stream", other)),
+        })
+        .transpose()
+    }
+}
+
+fn no_such_group(key: &str, group: &str) -> StoreError {
+    StoreError::NoSuchGroup {
+        key: key.to_string(),
+        group: group.to_string(),
+    }
+}
+
+/// Written field by field; the codec lives here because the fields are private.
+impl Persist for Stream {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.entries.encode(out);
+        self.last_id.encode(out);
+        self.groups.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Stream {
+            entries: Persist::decode(input)?,
+            last_id: Persist::decode(input)?,
+            groups: Persist::decode(input)?,
+        })
+    }
+}
+
+impl Persist for ConsumerGroup {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.last_delivered.encode(out);
+        self.pending.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(ConsumerGroup {
+            last_delivered: Persist::decode(input)?,
+            pending: Persist::decode(input)?,
+        })
+    }
+}
+
+impl Persist for PendingEntry {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.id.encode(out);
+        self.consumer.encode(out);
+        self.deliveries.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(PendingEntry {
+            id: Persist::decode(input)?,
+            consumer: Persist::decode(input)?,
+            deliveries: Persist::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::ChangeEvent;
+
+    fn fields(n: i64) -> HashMap<String, StoreValue> {
+        HashMap::from([("n".to_string(), StoreValue::Counter(n))])
+    }
+
+    #[test]
+    fn test_stream_append_range_and_trim() {
+        let store = ReactiveStore::new();
+        let ids: Vec<StreamId> = (0..5)
+            .map(|n| store.xadd("log", fields(n)).unwrap())
+            .collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(store.xlen("log"), Ok(5));
+
+        let middle = store.xrange("log", ids[1]..=ids[2]).unwrap();
+        assert_eq!(
+            middle,
+            vec![
+                StreamEntry {
+                    id: ids[1],
+                    fields: fields(1)
+                },
+                StreamEntry {
+                    id: ids[2],
+                    fields: fields(2)
+                },
+            ]
+        );
+
+        assert_eq!(store.xtrim("log", StreamTrim::MaxLen(2)), Ok(3));
+        assert_eq!(store.xrange("log", ..).unwrap()[0].id, ids[3]);
+        assert_eq!(
+            store.xtrim("log", StreamTrim::MaxAge(Duration::from_secs(60))),
+            Ok(0)
+        );
+        // Once their millisecond has passed, every entry is older than zero
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(
+            store.xtrim("log", StreamTrim::MaxAge(Duration::ZERO)),
+            Ok(2)
+        );
+        assert_eq!(store.xlen("log"), Ok(0));
+    }
+
+    #[tokio::test]
+    async fn test_read_after_waits_for_new_entries() {
+        let store = ReactiveStore::new();
+        let first = store.xadd("log", fields(1)).unwrap();
+
+        let reader = {
+            let store = store.clone();
+            tokio::spawn(async move { store.read_after("log", first, 10, None).await })
+        };
+        tokio::task::yield_now().await;
+        let second = store.xadd("log", fields(2)).unwrap();
+
+        let entries = reader.await.unwrap().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, second);
+        assert_eq!(
+            store
+                .read_after("log", second, 10, Some(Duration::from_millis(10)))
+                .await,
+            Ok(Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_consumer_groups_track_pending_entries() {
+        let store = ReactiveStore::new();
+        let mut sub = store.subscribe();
+        let ids: Vec<StreamId> = (0..3)
+            .map(|n| store.xadd("log", fields(n)).unwrap())
+            .collect();
+        assert_eq!(
+            store.xgroup_create("log", "workers", StreamId::MIN),
+            Ok(true)
+        );
+        assert_eq!(
+            store.xgroup_create("log", "workers", StreamId::MIN),
+            Ok(false)
+        );
+
+        let first = store.xreadgroup("log", "workers", "a", 2).unwrap();
+        let second = store.xreadgroup("log", "workers", "b", 2).unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(second[0].id, ids[2]);
+        assert!(store
+            .xreadgroup("log", "workers", "b", 2)
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(store.xack("log", "workers", &[ids[0], ids[0]]), Ok(1));
+        let pending = store.xpending("log", "workers").unwrap();
+        assert_eq!(
+            pending.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+            vec![ids[1], ids[2]]
+        );
+        assert_eq!(pending[1].consumer, "b");
+        assert_eq!(
+            store.xreadgroup("log", "missing", "a", 1),
+            Err(StoreError::NoSuchGroup {
+                key: "log".into(),
+                group: "missing".into()
+            })
+        );
+
+        // The published edits are enough to rebuild the stream and its groups
+        let mut copy = StoreValue::Stream(Stream::new());
+        while let Ok(ChangeEvent::Edited { edit, .. }) = sub.try_recv() {
+            edit.apply(&mut copy);
+        }
+        assert_eq!(Some(copy), store.get("log"));
+    }
+}
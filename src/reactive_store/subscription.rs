@@ -0,0 +1,345 @@
+use crate::reactive_store::{ChangeEvent, KeyFilter, StoreValue};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// What happens to events a subscriber has not consumed yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Each subscriber buffers up to the channel capacity. When it falls
+    /// further behind the oldest events are dropped and the next receive
+    /// reports [`RecvError::Lagged`], after which the subscriber can call
+    /// [`ReactiveStore::resync`](crate::reactive_store::ReactiveStore::resync).
+    #[default]
+    DropOldest,
+    /// Each subscriber gets an unbounded queue and never misses an event.
+    /// A subscriber that stops reading grows its queue without limit.
+    Unbounded,
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// `missed` events were dropped. Everything after revision `since` may
+    /// be incomplete until the subscription is resynchronised.
+    #[error("subscriber lagged and missed {missed} events after revision {since}")]
+    Lagged { missed: u64, since: u64 },
+    #[error("store was dropped")]
+    Closed,
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    #[error("no event is ready")]
+    Empty,
+    #[error("subscriber lagged and missed {missed} events after revision {since}")]
+    Lagged { missed: u64, since: u64 },
+    #[error("store was dropped")]
+    Closed,
+}
+
+/**
+ * Bounded single-subscriber queue that drops its oldest event when full and
+ * counts what it dropped.
+ */
+#[derive(Debug)]
+pub(crate) struct Ring<K, V> {
+    state: Mutex<RingState<K, V>>,
+}
+
+#[derive(Debug)]
+struct RingState<K, V> {
+    events: VecDeque<ChangeEvent<K, V>>,
+    capacity: usize,
+    missed: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<K, V> Ring<K, V> {
+    fn new(capacity: usize) -> Self {
+        Ring {
+            state: Mutex::new(RingState {
+                events: VecDeque::with_capacity(capacity),
+                capacity: capacity.max(1),
+                missed: 0,
+                closed: false,
+                waker: None,
+            }),
+        }
+    }
+
+    fn push(&self, event: ChangeEvent<K, V>) {
+        let mut state = self.state.lock().unwrap();
+        if state.events.len() == state.capacity {
+            state.events.pop_front();
+            state.missed += 1;
+        }
+        state.events.push_back(event);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn pull(&self, cx: Option<&mut Context<'_>>) -> Pull<K, V> {
+        let mut state = self.state.lock().unwrap();
+        if state.missed > 0 {
+            return Pull::Lagged(std::mem::take(&mut state.missed));
+        }
+        if let Some(event) = state.events.pop_front() {
+            return Pull::Event(event);
+        }
+        if state.closed {
+            return Pull::Closed;
+        }
+        if let Some(cx) = cx {
+            state.waker = Some(cx.waker().clone());
+        }
+        Pull::Empty
+    }
+
+    fn discard(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.events.clear();
+        state.missed = 0;
+    }
+}
+
+/// Result of taking the next item from an inlet.
+// Only ever moved straight to the caller, so boxing the event would just add an allocation
+#[allow(clippy::large_enum_variant)]
+enum Pull<K, V> {
+    Event(ChangeEvent<K, V>),
+    Lagged(u64),
+    Closed,
+    Empty,
+}
+
+/// The sending half of one subscription, held by the publisher.
+#[derive(Debug)]
+pub(crate) enum Outlet<K, V> {
+    Bounded(Arc<Ring<K, V>>),
+    Unbounded(mpsc::UnboundedSender<ChangeEvent<K, V>>),
+}
+
+impl<K: Clone, V: Clone> Outlet<K, V> {
+    pub(crate) fn channel(policy: OverflowPolicy, capacity: usize) -> (Self, Inlet<K, V>) {
+        match policy {
+            OverflowPolicy::DropOldest => {
+                let ring = Arc::new(Ring::new(capacity));
+                (Outlet::Bounded(ring.clone()), Inlet::Bounded(ring))
+            }
+            OverflowPolicy::Unbounded => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                (Outlet::Unbounded(tx), Inlet::Unbounded(rx))
+            }
+        }
+    }
+
+    /// Sends `event`, returning false once the receiving side is gone.
+    pub(crate) fn send(&self, event: &ChangeEvent<K, V>) -> bool {
+        match self {
+            Outlet::Bounded(ring) => {
+                // The only other reference is held by the subscription
+                let alive = Arc::strong_count(ring) > 1;
+                if alive {
+                    ring.push(event.clone());
+                }
+                alive
+            }
+            Outlet::Unbounded(tx) => tx.send(event.clone()).is_ok(),
+        }
+    }
+}
+
+impl<K, V> Drop for Outlet<K, V> {
+    fn drop(&mut self) {
+        if let Outlet::Bounded(ring) = self {
+            ring.close();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Inlet<K, V> {
+    Bounded(Arc<Ring<K, V>>),
+    Unbounded(mpsc::UnboundedReceiver<ChangeEvent<K, V>>),
+}
+
+impl<K, V> Inlet<K, V> {
+    fn pull(&mut self, cx: Option<&mut Context<'_>>) -> Pull<K, V> {
+        match self {
+            Inlet::Bounded(ring) => ring.pull(cx),
+            Inlet::Unbounded(rx) => match cx {
+                Some(cx) => match rx.poll_recv(cx) {
+                    Poll::Ready(Some(event)) => Pull::Event(event),
+                    Poll::Ready(None) => Pull::Closed,
+                    Poll::Pending => Pull::Empty,
+                },
+                None => match rx.try_recv() {
+                    Ok(event) => Pull::Event(event),
+                    Err(mpsc::error::TryRecvError::Empty) => Pull::Empty,
+                    Err(mpsc::error::TryRecvError::Disconnected) => Pull::Closed,
+                },
+            },
+        }
+    }
+}
+
+/**
+ * A stream of change events from a store, optionally restricted by a
+ * [`KeyFilter`].
+ *
+ * The subscription remembers the last revision it handed out and silently
+ * skips anything at or before it, which is what makes
+ * [`ReactiveStore::resync`](crate::reactive_store::ReactiveStore::resync)
+ * safe: events already covered by the snapshot are not delivered twice.
+ *
+ * It also implements [`Stream`], with the combinators of
+ * [`EventStream`](crate::reactive_store::EventStream). Consumed that way a lag is not an
+ * error: the stream carries on and the number of dropped events is
+ * available from [`Subscription::missed`].
+ */
+#[derive(Debug)]
+pub struct Subscription<K = String, V = StoreValue> {
+    inlet: Inlet<K, V>,
+    filter: Option<KeyFilter<K>>,
+    /// Events replayed from the change log, delivered before live ones.
+    replay: VecDeque<ChangeEvent<K, V>>,
+    last_revision: u64,
+    missed: u64,
+}
+
+impl<K, V> Subscription<K, V> {
+    /**
+     * `revision` is the last revision the subscriber is considered to have
+     * seen. The replayed events must all come after it and before anything
+     * the inlet will receive.
+     */
+    pub(crate) fn new(
+        inlet: Inlet<K, V>,
+        filter: Option<KeyFilter<K>>,
+        revision: u64,
+        replay: VecDeque<ChangeEvent<K, V>>,
+    ) -> Self {
+        Subscription {
+            inlet,
+            filter,
+            replay,
+            last_revision: revision,
+            missed: 0,
+        }
+    }
+
+    pub async fn recv(&mut self) -> Result<ChangeEvent<K, V>, RecvError> {
+        std::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn poll_recv(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<ChangeEvent<K, V>, RecvError>> {
+        match self.next_event(Some(cx)) {
+            Pull::Event(event) => Poll::Ready(Ok(event)),
+            Pull::Lagged(missed) => Poll::Ready(Err(RecvError::Lagged {
+                missed,
+                since: self.last_revision,
+            })),
+            Pull::Closed => Poll::Ready(Err(RecvError::Closed)),
+            Pull::Empty => Poll::Pending,
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<ChangeEvent<K, V>, TryRecvError> {
+        match self.next_event(None) {
+            Pull::Event(event) => Ok(event),
+            Pull::Lagged(missed) => Err(TryRecvError::Lagged {
+                missed,
+                since: self.last_revision,
+            }),
+            Pull::Closed => Err(TryRecvError::Closed),
+            Pull::Empty => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// The filter this subscription was created with, `None` for every key.
+    pub fn filter(&self) -> Option<&KeyFilter<K>> {
+        self.filter.as_ref()
+    }
+
+    /// The revision of the last event delivered or skipped by a resync.
+    pub fn last_revision(&self) -> u64 {
+        self.last_revision
+    }
+
+    /// Events dropped while the subscription was consumed as a stream.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /**
+     * Discards everything queued so far and skips any later event at or
+     * before `revision`. Callers hold the data lock so nothing newer than
+     * `revision` can be queued while this runs.
+     */
+    pub(crate) fn resume_after(&mut self, revision: u64) {
+        self.replay.clear();
+        match &mut self.inlet {
+            Inlet::Bounded(ring) => ring.discard(),
+            Inlet::Unbounded(rx) => while rx.try_recv().is_ok() {},
+        }
+        self.last_revision = self.last_revision.max(revision);
+    }
+
+    fn next_event(&mut self, mut cx: Option<&mut Context<'_>>) -> Pull<K, V> {
+        if let Some(event) = self.replay.pop_front() {
+            self.last_revision = event.revision();
+            return Pull::Event(event);
+        }
+        loop {
+            match self.inlet.pull(cx.as_deref_mut()) {
+                Pull::Event(event) if event.revision() <= self.last_revision => continue,
+                Pull::Event(event) => {
+                    self.last_revision = event.revision();
+                    return Pull::Event(event);
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+// Nothing in a subscription is ever pinned in place
+impl<K, V> Unpin for Subscription<K, V> {}
+
+impl<K, V> Stream for Subscription<K, V> {
+    type Item = ChangeEvent<K, V>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ChangeEvent<K, V>>> {
+        loop {
+            return match self.poll_recv(cx) {
+                Poll::Ready(Ok(event)) => Poll::Ready(Some(event)),
+                Poll::Ready(Err(RecvError::Lagged { missed, .. })) => {
+                    self.missed += missed;
+                    continue;
+                }
+                Poll::Ready(Err(RecvError::Closed)) => Poll::Ready(None),
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use crate::reactive_store::{
+    Change, ChangeEvent, Entry, ReactiveStore, StoreData, StoreError, StoreKey, StoreValue,
+};
+use std::borrow::Borrow;
+use std::collections::{hash_map, HashMap};
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+use thiserror::Error;
+
+/// A watched key changed between [`ReactiveStore::watch`] and [`Watch::exec`].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("transaction aborted, watched key {key:?} changed")]
+pub struct TransactionAborted<K = String> {
+    pub key: K,
+}
+
+/// Why [`Watch::exec`] applied none of the queued writes.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ExecError<K = String> {
+    #[error(transparent)]
+    Aborted(#[from] TransactionAborted<K>),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/**
+ * The view of the store inside [`ReactiveStore::transaction`]. Reads see
+ * the store as it was when the transaction started plus the transaction's
+ * own writes. Writes are staged and only reach the store on commit.
+ */
+#[derive(Debug)]
+pub struct Transaction<'a, K = String, V = StoreValue> {
+    data: &'a HashMap<K, Entry<V>>,
+    now: Instant,
+    /// Staged writes, `None` for a removal.
+    writes: HashMap<K, Option<V>>,
+    /// Keys in the order they were first written.
+    order: Vec<K>,
+}
+
+impl<'a, K: StoreKey, V: StoreData> Transaction<'a, K, V> {
+    pub(crate) fn new(data: &'a HashMap<K, Entry<V>>) -> Self {
+        Transaction {
+            data,
+            now: Instant::now(),
+            writes: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.writes.get(key) {
+            Some(staged) => staged.clone(),
+            None => self.stored(key).cloned(),
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.writes.get(key) {
+            Some(staged) => staged.is_some(),
+            None => self.stored(key).is_some(),
+        }
+    }
+
+    /// Stages a write of `key`. Like [`ReactiveStore::set`] it clears any TTL.
+    pub fn set<Q>(&mut self, key: &Q, value: V)
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.stage(key.to_owned(), Some(value));
+    }
+
+    /// Stages the removal of `key`, returning the value it had.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let old = self.get(key);
+        self.stage(key.to_owned(), None);
+        old
+    }
+
+    /// Stages `value` for `key`, `None` to remove it.
+    pub(crate) fn stage(&mut self, key: K, value: Option<V>) {
+        match self.writes.entry(key) {
+            hash_map::Entry::Occupied(mut staged) => *staged.get_mut() = value,
+            hash_map::Entry::Vacant(slot) => {
+                self.order.push(slot.key().clone());
+                slot.insert(value);
+            }
+        }
+    }
+
+    /// The revision of `key` in the store, 0 if it does not exist.
+    fn revision(&self, key: &K) -> u64 {
+        self.data
+            .get(key)
+            .filter(|entry| !entry.is_expired(self.now))
+            .map_or(0, |entry| entry.revision)
+    }
+
+    /// The live value of `key` in the store, ignoring staged writes.
+    fn stored<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.data
+            .get(key)
+            .filter(|entry| !entry.is_expired(self.now))
+            .map(|entry| &entry.value)
+    }
+
+    /// The staged writes in order, without removals of keys the store lacks.
+    pub(crate) fn into_writes(mut self) -> Vec<(K, Option<V>)> {
+        let order = std::mem::take(&mut self.order);
+        order
+            .into_iter()
+            .filter_map(|key| {
+                let value = self.writes.remove(&key)?;
+                (value.is_some() || self.stored(&key).is_some()).then_some((key, value))
+            })
+            .collect()
+    }
+}
+
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    /**
+     * Runs `f` as a transaction and commits its writes if it returns `Ok`.
+     * On `Err` nothing is written, and neither is anything if `f` panics:
+     * the panic carries on once the lock is released, leaving the store
+     * usable. A store refusing writes returns its error as an `E` without
+     * running `f`.
+     *
+     * The store is locked for writing while `f` runs, so transactions are
+     * serialised with every other write. `f` must not call back into the
+     * store, not even to read, as that deadlocks. The committed changes
+     * are published as a single [`ChangeEvent::Committed`] event. Derived
+     * keys and blocked pops follow with events of their own.
+     */
+    pub fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Transaction<'_, K, V>) -> Result<T, E>,
+        E: From<StoreError>,
+    {
+        let mut data = self.data.write().unwrap();
+        self.writable()?;
+        let mut tx = Transaction::new(&data);
+        let output = match panic::catch_unwind(AssertUnwindSafe(|| f(&mut tx))) {
+            Ok(output) => output?,
+            Err(payload) => {
+                // Unlocking before the panic resumes keeps the lock from being poisoned
+                drop(tx);
+                drop(data);
+                panic::resume_unwind(payload)
+            }
+        };
+        let writes = tx.into_writes();
+        let committed = self.commit_locked(&mut data, writes, None);
+        // Likewise for a compute closure of a derived key, see `writing`
+        let panicked = self.take_panic();
+        drop(data);
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        committed?;
+        Ok(output)
+    }
+
+    /**
+     * Applies `writes`, at most one per key, to the already locked map and
+     * publishes them as one [`ChangeEvent::Committed`] event. Written keys
+     * get the deadline `expires_at`. Returns the revision of the commit,
+     * `None` if there was nothing to write. If the log fails to record the
+     * commit, none of it is applied.
+     */
+    pub(crate) fn commit_locked(
+        &self,
+        data: &mut HashMap<K, Entry<V>>,
+        writes: Vec<(K, Option<V>)>,
+        expires_at: Option<Instant>,
+    ) -> Result<Option<u64>, StoreError> {
+        if writes.is_empty() {
+            return Ok(None);
+        }
+
+        let now = Instant::now();
+        let keys: Vec<K> = writes.iter().map(|(key, _)| key.clone()).collect();
+        let backups: Vec<_> = keys.iter().map(|key| self.backup(data, key)).collect();
+        let published = self.events.try_publish(|revision| {
+            let changes = writes
+                .into_iter()
+                .filter_map(|(key, value)| match value {
+                    Some(new) => {
+                        let entry = Entry {
+                            value: new.clone(),
+                            expires_at,
+                            revision,
+                        };
+                        let old = data
+                            .insert(key.clone(), entry)
+                            .filter(|entry| !entry.is_expired(now))
+                            .map(|entry| entry.value);
+                        Some(Change::Set {
+                            key,
+                            old,
+                            new,
+                            expires_at,
+                        })
+                    }
+                    None => data.remove(&key).map(|entry| Change::Removed {
+                        key,
+                        old: entry.value,
+                    }),
+                })
+                .collect();
+            ChangeEvent::Committed { revision, changes }
+        });
+        let revision = match published {
+            Ok(revision) => revision,
+            Err(error) => {
+                for (key, backup) in keys.into_iter().zip(backups) {
+                    Self::restore(data, key, backup);
+                }
+                return Err(error.into());
+            }
+        };
+        for key in &keys {
+            self.propagate(data, key);
+            self.serve_blocked(data, key);
+        }
+        Ok(Some(revision))
+    }
+}
+
+/**
+ * A queue of writes that [`Watch::exec`] applies only if none of the
+ * watched keys changed since they were watched, like `WATCH`, `MULTI` and
+ * `EXEC`. No lock is held in between, so the handle can live across awaits
+ * while the caller reads the store and decides what to write.
+ *
+ * A key changes when its value is written, removed or expires; TTL updates
+ * do not count. A key that is absent both when watched and at `exec` counts
+ * as unchanged, even if it existed in between.
+ */
+#[derive(Debug)]
+pub struct Watch<K = String, V = StoreValue> {
+    store: ReactiveStore<K, V>,
+    /// Watched keys and their revisions when watched.
+    watched: Vec<(K, u64)>,
+    /// Queued writes, `None` for a removal.
+    queued: Vec<(K, Option<V>)>,
+}
+
+impl<K: StoreKey, V: StoreData> Watch<K, V> {
+    /// Adds `key` to the watched keys.
+    pub fn watch<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let revision = self
+            .store
+            .get_versioned(key)
+            .map_or(0, |current| current.revision);
+        self.watched.push((key.to_owned(), revision));
+    }
+
+    /// Queues a write of `key`.
+    pub fn set<Q>(&mut self, key: &Q, value: V)
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.queued.push((key.to_owned(), Some(value)));
+    }
+
+    /// Queues the removal of `key`.
+    pub fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.queued.push((key.to_owned(), None));
+    }
+
+    /**
+     * Applies the queued writes as one transaction, or none of them if a
+     * watched key changed.
+     */
+    pub fn exec(self) -> Result<(), ExecError<K>> {
+        let Watch {
+            store,
+            watched,
+            queued,
+        } = self;
+        store.transaction(|tx| {
+            if let Some((key, _)) = watched
+                .into_iter()
+                .find(|(key, revision)| tx.revision(key) != *revision)
+            {
+                return Err(TransactionAborted { key }.into());
+            }
+            for (key, value) in queued {
+                tx.stage(key, value);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    /**
+     * Watches `keys` and returns the handle to queue writes on. Dropping the
+     * handle discards them.
+     */
+    pub fn watch<Q>(&self, keys: &[&Q]) -> Watch<K, V>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let mut watch = Watch {
+            store: self.clone(),
+            watched: Vec::with_capacity(keys.len()),
+            queued: Vec::new(),
+        };
+        for key in keys {
+            watch.watch(*key);
+        }
+        watch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::{StoreError, TryRecvError};
+
+    #[test]
+    fn test_transaction_commits_one_event() {
+        let store = ReactiveStore::new();
+        store
+            .set("todo", StoreValue::List(vec!["a".into(), "b".into()]))
+            .unwrap();
+        store.set("stale", StoreValue::Null).unwrap();
+        let mut all = store.subscribe();
+        let mut done = store.subscribe_key("done");
+        let mut other = store.subscribe_key("other");
+
+        // Move the first item from one list to the other
+        let moved = store
+            .transaction(|tx| {
+                let Some(StoreValue::List(mut todo)) = tx.get("todo") else {
+                    return Err(StoreError::NoSuchKey { key: "todo".into() });
+                };
+                let item = todo.remove(0);
+                let mut done = match tx.get("done") {
+                    Some(StoreValue::List(done)) => done,
+                    _ => Vec::new(),
+                };
+                done.push(item.clone());
+                tx.set("todo", todo.into());
+                tx.set("done", done.into());
+                tx.remove("stale");
+                tx.remove("missing");
+                assert_eq!(tx.get("done"), Some(StoreValue::List(vec![item.clone()])));
+                Ok(item)
+            })
+            .unwrap();
+        assert_eq!(moved, StoreValue::Text("a".into()));
+        assert_eq!(store.get("todo"), Some(StoreValue::List(vec!["b".into()])));
+        assert!(store.get("stale").is_none());
+
+        let event = all.try_recv().unwrap();
+        let ChangeEvent::Committed { revision, changes } = &event else {
+            panic!("expected a committed transaction, got {event:?}");
+        };
+        assert_eq!(*revision, 3);
+        let keys: Vec<&str> = changes.iter().map(|change| change.key().as_str()).collect();
+        assert_eq!(keys, ["todo", "done", "stale"]);
+        assert_eq!(all.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(done.try_recv(), Ok(event));
+        assert_eq!(other.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(store.get_versioned("done").unwrap().revision, 3);
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_on_error() {
+        let store = ReactiveStore::new();
+        store.set("a", StoreValue::Counter(1)).unwrap();
+        let mut events = store.subscribe();
+
+        let missing = StoreError::NoSuchKey { key: "c".into() };
+        let result: Result<(), StoreError> = store.transaction(|tx| {
+            tx.set("a", StoreValue::Counter(2));
+            tx.set("b", StoreValue::Counter(3));
+            Err(missing.clone())
+        });
+        assert_eq!(result, Err(missing));
+        assert_eq!(store.get("a"), Some(StoreValue::Counter(1)));
+        assert!(store.get("b").is_none());
+        assert_eq!(events.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(store.revision(), 1);
+    }
+
+    #[test]
+    fn test_transaction_panic_leaves_store_usable() {
+        let store = ReactiveStore::new();
+        store.set("a", StoreValue::Counter(1)).unwrap();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.transaction(|tx| -> Result<(), StoreError> {
+                tx.set("a", StoreValue::Counter(2));
+                panic!("bug in the transaction");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(store.get("a"), Some(StoreValue::Counter(1)));
+        store.set("b", StoreValue::Counter(3)).unwrap();
+        assert_eq!(store.revision(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_watch_aborts_on_change() {
+        let store = ReactiveStore::new();
+        store.set("a", StoreValue::Counter(1)).unwrap();
+
+        let mut watch = store.watch(&["a", "b"]);
+        tokio::task::yield_now().await;
+        watch.set("a", StoreValue::Counter(2));
+        watch.remove("c");
+        store
+            .touch("a", std::time::Duration::from_secs(60))
+            .unwrap();
+        store.set("b", StoreValue::Counter(0)).unwrap();
+        assert_eq!(
+            watch.exec(),
+            Err(TransactionAborted { key: "b".into() }.into())
+        );
+        assert_eq!(store.get("a"), Some(StoreValue::Counter(1)));
+
+        let mut events = store.subscribe();
+        let mut watch = store.watch(&["a", "b"]);
+        watch.set("a", StoreValue::Counter(2));
+        watch.set("b", StoreValue::Counter(3));
+        assert_eq!(watch.exec(), Ok(()));
+        assert_eq!(store.get("b"), Some(StoreValue::Counter(3)));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ChangeEvent::Committed { changes, .. }) if changes.len() == 2
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::reactive_store::{SortedSet, StoreValue, Stream};
+use std::collections::{HashMap, HashSet};
+
+/**
+ * Values are equal when they have the same variant and contents. Floats
+ * are equal when their bits are, and every NaN equals every other, so that
+ * writing the same value twice is recognised as no change while writing
+ * -0.0 over 0.0 is not.
+ */
+impl PartialEq for StoreValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StoreValue::Map(a), StoreValue::Map(b)) => a == b,
+            (StoreValue::List(a), StoreValue::List(b)) => a == b,
+            (StoreValue::Set(a), StoreValue::Set(b)) => a == b,
+            (StoreValue::Counter(a), StoreValue::Counter(b)) => a == b,
+            (StoreValue::Text(a), StoreValue::Text(b)) => a == b,
+            (StoreValue::SortedSet(a), StoreValue::SortedSet(b)) => a == b,
+            (StoreValue::Stream(a), StoreValue::Stream(b)) => a == b,
+            (StoreValue::Bytes(a), StoreValue::Bytes(b)) => a == b,
+            (StoreValue::Float(a), StoreValue::Float(b)) => {
+                a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan())
+            }
+            (StoreValue::Bool(a), StoreValue::Bool(b)) => a == b,
+            (StoreValue::Null, StoreValue::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<HashMap<String, StoreValue>> for StoreValue {
+    fn from(value: HashMap<String, StoreValue>) -> Self {
+        StoreValue::Map(value)
+    }
+}
+
+impl From<Vec<StoreValue>> for StoreValue {
+    fn from(value: Vec<StoreValue>) -> Self {
+        StoreValue::List(value)
+    }
+}
+
+impl From<HashSet<String>> for StoreValue {
+    fn from(value: HashSet<String>) -> Self {
+        StoreValue::Set(value)
+    }
+}
+
+impl From<i64> for StoreValue {
+    fn from(value: i64) -> Self {
+        StoreValue::Counter(value)
+    }
+}
+
+impl From<String> for StoreValue {
+    fn from(value: String) -> Self {
+        StoreValue::Text(value)
+    }
+}
+
+impl From<&str> for StoreValue {
+    fn from(value: &str) -> Self {
+        StoreValue::Text(value.to_string())
+    }
+}
+
+impl From<SortedSet> for StoreValue {
+    fn from(value: SortedSet) -> Self {
+        StoreValue::SortedSet(value)
+    }
+}
+
+impl From<Stream> for StoreValue {
+    fn from(value: Stream) -> Self {
+        StoreValue::Stream(value)
+    }
+}
+
+impl From<Vec<u8>> for StoreValue {
+    fn from(value: Vec<u8>) -> Self {
+        StoreValue::Bytes(value)
+    }
+}
+
+impl From<&[u8]> for StoreValue {
+    fn from(value: &[u8]) -> Self {
+        StoreValue::Bytes(value.to_vec())
+    }
+}
+
+impl From<f64> for StoreValue {
+    fn from(value: f64) -> Self {
+        StoreValue::Float(value)
+    }
+}
+
+impl From<bool> for StoreValue {
+    fn from(value: bool) -> Self {
+        StoreValue::Bool(value)
+    }
+}
+
+/// `None` becomes [`StoreValue::Null`].
+impl<T: Into<StoreValue>> From<Option<T>> for StoreValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(StoreValue::Null, Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_float_equality_treats_nan_as_equal() {
+        assert_eq!(StoreValue::Float(f64::NAN), StoreValue::Float(f64::NAN));
+        assert_ne!(StoreValue::Float(0.0), StoreValue::Float(-0.0));
+        assert_eq!(StoreValue::Float(-0.0), StoreValue::Float(-0.0));
+        assert_ne!(StoreValue::Float(1.0), StoreValue::Counter(1));
+        assert_eq!(
+            StoreValue::List(vec![StoreValue::Float(f64::NAN), StoreValue::Null]),
+            StoreValue::List(vec![f64::NAN.into(), None::<bool>.into()])
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(StoreValue::from(true), StoreValue::Bool(true));
+        assert_eq!(
+            StoreValue::from(&b"\x00\xff"[..]),
+            StoreValue::Bytes(vec![0, 255])
+        );
+        assert_eq!(StoreValue::from("text"), StoreValue::Text("text".into()));
+        assert_eq!(StoreValue::from(Some(7)), StoreValue::Counter(7));
+        assert_eq!(StoreValue::Null.kind(), "null");
+    }
+}
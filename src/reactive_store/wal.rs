@@ -0,0 +1,1210 @@
+use crate::reactive_store::snapshot::{self, Saved};
+use crate::reactive_store::{
+    Change, ChangeEvent, DecodeError, Edit, Entry, Persist, ReactiveStore, StoreConfig, StoreData,
+    StoreKey, WeakStore,
+};
+use std::collections::{hash_map, HashMap};
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fs, mem, thread};
+use thiserror::Error;
+
+/// Identifies a store log and the version of its record format.
+const MAGIC: &[u8; 8] = b"rrlog\0\0\x01";
+
+/// Length and checksum in front of every record.
+const FRAME: usize = 8;
+
+/**
+ * When appended records are forced to disk. Records are appended while the
+ * store is locked for writing, so that the log holds them in revision
+ * order; the policy decides whether the sync happens under that lock too.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Before the write that produced the record returns. Every reader and
+    /// writer of the store waits for the sync, so writes are only as fast
+    /// as the disk.
+    Always,
+    /// By a background thread, at most this long after the write. Records
+    /// still reach the operating system immediately, so only a machine
+    /// crash can lose them.
+    Every(Duration),
+    /// Whenever the operating system decides to.
+    Never,
+}
+
+/**
+ * When the log is rewritten in the background, see
+ * [`ReactiveStore::compact`]. Both conditions must hold.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionTrigger {
+    /// Ratio of the log size to its size after the last rewrite, or after
+    /// opening it.
+    pub growth: f64,
+    /// Size in bytes below which the log is left alone.
+    pub min_size: u64,
+}
+
+impl Default for CompactionTrigger {
+    /// Once the log has doubled and holds at least 64 MiB.
+    fn default() -> Self {
+        CompactionTrigger {
+            growth: 2.0,
+            min_size: 64 << 20,
+        }
+    }
+}
+
+/// Where and how [`ReactiveStore::open`] keeps its log.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub path: PathBuf,
+    pub fsync: FsyncPolicy,
+    /// `None` only rewrites the log on [`ReactiveStore::compact`].
+    pub compaction: Option<CompactionTrigger>,
+}
+
+impl LogConfig {
+    /// A log at `path`, synced once a second and compacted by default.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LogConfig {
+            path: path.into(),
+            fsync: FsyncPolicy::Every(Duration::from_secs(1)),
+            compaction: Some(CompactionTrigger::default()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LogError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{} is not a store log", path.display())]
+    NotALog { path: PathBuf },
+    /// A record passed its checksum but could not be decoded, for example
+    /// because it was written for another key or value type.
+    #[error("record at offset {offset} is malformed: {source}")]
+    Malformed { offset: u64, source: DecodeError },
+    /// The record at `offset` is damaged but intact records follow it, so
+    /// it is not the remains of an interrupted write.
+    #[error("log is damaged at offset {offset}, before intact records")]
+    Corrupt { offset: u64 },
+}
+
+/// What [`ReactiveStore::open`] found in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recovery {
+    /// Records replayed.
+    pub records: u64,
+    /// Bytes cut from the end of the log because they did not form a
+    /// complete record with a valid checksum, as left by a crash mid-write.
+    /// Damage anywhere else fails [`ReactiveStore::open`] instead.
+    pub truncated: u64,
+}
+
+/// Receives every published event, in revision order.
+pub(crate) trait EventLog<K, V>: Debug + Send + Sync {
+    /**
+     * Records `event`. An event that fails is in neither the log nor a
+     * rewrite in progress, and every later append fails as well until a
+     * rewrite succeeds.
+     */
+    fn append(&self, event: &ChangeEvent<K, V>) -> Result<(), LogError>;
+
+    /// Copies an event that [`EventLog::append`] failed into a rewrite in progress.
+    fn copy_aside(&self, event: &ChangeEvent<K, V>);
+
+    /// Fails once an append or sync has failed, until a rewrite succeeds.
+    fn check(&self) -> Result<(), LogError>;
+
+    /// Forces everything appended so far to disk.
+    fn sync(&self) -> Result<(), LogError>;
+
+    /// Held for the whole of a rewrite, so that only one runs at a time.
+    fn rewriting(&self) -> MutexGuard<'_, ()>;
+
+    /// Starts copying appended records aside for a rewrite.
+    fn begin_rewrite(&self);
+
+    /**
+     * Replaces the log with `entries`, the keyspace at `revision` when the
+     * rewrite began, followed by every record appended since.
+     */
+    fn finish_rewrite(&self, revision: u64, entries: Vec<Saved<K, V>>) -> Result<(), LogError>;
+
+    /// Keeps the error of a failed background compaction until a rewrite succeeds.
+    fn compaction_failed(&self, error: LogError);
+
+    /// Takes the error kept by [`EventLog::compaction_failed`].
+    fn take_compaction_error(&self) -> Option<LogError>;
+}
+
+/**
+ * Append-only file of framed records, one per event. Each frame is the
+ * payload length and its CRC-32, both little endian `u32`s, followed by
+ * the payload: the revision, a tag and the fields of the change. Old
+ * values are not written, and deadlines are stored as wall clock
+ * milliseconds so they survive a restart. A rewritten log starts with a
+ * single record holding the whole keyspace.
+ */
+#[derive(Debug)]
+pub(crate) struct Wal {
+    file: Mutex<LogFile>,
+    path: PathBuf,
+    fsync: FsyncPolicy,
+    compaction: Option<CompactionTrigger>,
+    /// Wakes the thread that compacts the log in the background.
+    compact: SyncSender<()>,
+    rewriting: Mutex<()>,
+    /// Why the last background compaction failed, if none succeeded since.
+    compaction_error: Mutex<Option<LogError>>,
+}
+
+#[derive(Debug)]
+struct LogFile {
+    file: File,
+    /// Reused for every record.
+    buffer: Vec<u8>,
+    /// Records were written since the last sync.
+    dirty: bool,
+    /// The first write that failed. The log no longer matches the store
+    /// after it, so nothing more is appended until it is rewritten.
+    failed: Option<io::Error>,
+    size: u64,
+    /// Size right after opening or the last rewrite.
+    base: u64,
+    /// Frames appended since a rewrite began, for the rewritten log.
+    copied: Option<Vec<u8>>,
+}
+
+impl LogFile {
+    fn check(&self) -> Result<(), LogError> {
+        match &self.failed {
+            Some(error) => Err(io::Error::new(error.kind(), error.to_string()).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Wal {
+    fn new(
+        file: File,
+        size: u64,
+        config: LogConfig,
+        compact: SyncSender<()>,
+    ) -> io::Result<Arc<Self>> {
+        let fsync = config.fsync;
+        let wal = Arc::new(Wal {
+            file: Mutex::new(LogFile {
+                file,
+                buffer: Vec::new(),
+                dirty: false,
+                failed: None,
+                size,
+                base: size,
+                copied: None,
+            }),
+            path: config.path,
+            fsync,
+            compaction: config.compaction,
+            compact,
+            rewriting: Mutex::new(()),
+            compaction_error: Mutex::new(None),
+        });
+        if let FsyncPolicy::Every(interval) = fsync {
+            let wal = Arc::downgrade(&wal);
+            thread::Builder::new()
+                .name("r-reactive-fsync".into())
+                .spawn(move || sync_periodically(wal, interval))?;
+        }
+        Ok(wal)
+    }
+
+    fn sync_if_dirty(&self) {
+        let mut log = self.file.lock().unwrap();
+        if log.dirty && log.failed.is_none() {
+            if let Err(error) = log.file.sync_data() {
+                log.failed = Some(error);
+            }
+            log.dirty = false;
+        }
+    }
+
+    /**
+     * Writes the new log at `partial`. Records appended meanwhile are
+     * caught up without the lock, and only the last few are copied while
+     * appends wait for the new log to be renamed into place. The new log
+     * holds everything the store does, so it also clears an earlier
+     * failure.
+     */
+    fn rewrite<K: Persist, V: Persist>(
+        &self,
+        partial: &Path,
+        revision: u64,
+        entries: Vec<Saved<K, V>>,
+    ) -> Result<(), LogError> {
+        let mut bytes = MAGIC.to_vec();
+        write_frame(&mut bytes, |out| {
+            revision.encode(out);
+            out.push(6);
+            entries.encode(out);
+        });
+        drop(entries);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(partial)?;
+        file.write_all(&bytes)?;
+        let mut size = bytes.len() as u64;
+
+        let copied = self
+            .file
+            .lock()
+            .unwrap()
+            .copied
+            .as_mut()
+            .map(mem::take)
+            .unwrap_or_default();
+        file.write_all(&copied)?;
+        file.sync_data()?;
+        size += copied.len() as u64;
+
+        let mut log = self.file.lock().unwrap();
+        let copied = log.copied.take().unwrap_or_default();
+        file.write_all(&copied)?;
+        file.sync_data()?;
+        size += copied.len() as u64;
+        fs::rename(partial, &self.path)?;
+        log.file = file;
+        log.size = size;
+        log.base = size;
+        log.dirty = false;
+        log.failed = None;
+        Ok(())
+    }
+}
+
+/// Runs on its own thread until the log is dropped.
+fn sync_periodically(wal: Weak<Wal>, interval: Duration) {
+    loop {
+        thread::sleep(interval);
+        let Some(wal) = wal.upgrade() else {
+            return;
+        };
+        wal.sync_if_dirty();
+    }
+}
+
+impl Drop for Wal {
+    fn drop(&mut self) {
+        if self.fsync != FsyncPolicy::Never {
+            self.sync_if_dirty();
+        }
+    }
+}
+
+impl<K: Persist, V: Persist> EventLog<K, V> for Wal {
+    fn append(&self, event: &ChangeEvent<K, V>) -> Result<(), LogError> {
+        let mut log = self.file.lock().unwrap();
+        log.check()?;
+        let LogFile {
+            file,
+            buffer,
+            failed,
+            size,
+            copied,
+            ..
+        } = &mut *log;
+        buffer.clear();
+        write_frame(buffer, |out| encode_event(event, out));
+        let written = file.write_all(buffer).and_then(|()| match self.fsync {
+            FsyncPolicy::Always => file.sync_data(),
+            _ => Ok(()),
+        });
+        if let Err(error) = written {
+            // The write is undone, so a record that made it to the file must
+            // not be replayed. Should this fail too, the original error is
+            // the one reported.
+            let _ = file.set_len(*size);
+            *failed = Some(error);
+            return log.check();
+        }
+        *size += buffer.len() as u64;
+        if let Some(copied) = copied {
+            copied.extend_from_slice(buffer);
+        }
+        log.dirty = self.fsync != FsyncPolicy::Always;
+
+        let grown = self.compaction.is_some_and(|trigger| {
+            log.size >= trigger.min_size && log.size as f64 >= log.base as f64 * trigger.growth
+        });
+        if grown && log.copied.is_none() {
+            // Already requested if the channel is full
+            let _ = self.compact.try_send(());
+        }
+        Ok(())
+    }
+
+    fn copy_aside(&self, event: &ChangeEvent<K, V>) {
+        if let Some(copied) = &mut self.file.lock().unwrap().copied {
+            write_frame(copied, |out| encode_event(event, out));
+        }
+    }
+
+    fn check(&self) -> Result<(), LogError> {
+        self.file.lock().unwrap().check()
+    }
+
+    fn sync(&self) -> Result<(), LogError> {
+        let mut log = self.file.lock().unwrap();
+        log.check()?;
+        if let Err(error) = log.file.sync_data() {
+            log.failed = Some(error);
+            return log.check();
+        }
+        log.dirty = false;
+        Ok(())
+    }
+
+    fn rewriting(&self) -> MutexGuard<'_, ()> {
+        self.rewriting.lock().unwrap()
+    }
+
+    fn begin_rewrite(&self) {
+        self.file.lock().unwrap().copied = Some(Vec::new());
+    }
+
+    fn finish_rewrite(&self, revision: u64, entries: Vec<Saved<K, V>>) -> Result<(), LogError> {
+        let mut partial = self.path.as_os_str().to_owned();
+        partial.push(".rewrite");
+        let rewritten = self.rewrite(Path::new(&partial), revision, entries);
+        if rewritten.is_err() {
+            self.file.lock().unwrap().copied = None;
+            let _ = fs::remove_file(&partial);
+        } else {
+            *self.compaction_error.lock().unwrap() = None;
+        }
+        rewritten
+    }
+
+    fn compaction_failed(&self, error: LogError) {
+        *self.compaction_error.lock().unwrap() = Some(error);
+    }
+
+    fn take_compaction_error(&self) -> Option<LogError> {
+        self.compaction_error.lock().unwrap().take()
+    }
+}
+
+/// Runs on its own thread until the store is dropped.
+fn compact_on_request<K: StoreKey, V: StoreData>(store: WeakStore<K, V>, requests: Receiver<()>) {
+    while requests.recv().is_ok() {
+        let Some(store) = store.upgrade() else {
+            return;
+        };
+        let Some(log) = store.events.log() else {
+            return;
+        };
+        // A failed rewrite leaves the current log in place, and the next
+        // request retries it. The error is kept under the rewrite lock, so
+        // that a later rewrite always clears it.
+        let _rewriting = log.rewriting();
+        if let Err(error) = store.compact_locked(log) {
+            log.compaction_failed(error);
+        }
+    }
+}
+
+/// A logged value and its deadline in wall clock milliseconds.
+type Logged<V> = (V, Option<u64>);
+
+/// A decoded record, applied to the map being rebuilt.
+enum Record<K, V> {
+    Set {
+        key: K,
+        value: V,
+        expires_at: Option<u64>,
+    },
+    Removed {
+        key: K,
+    },
+    Edited {
+        key: K,
+        edit: Edit,
+    },
+    TtlChanged {
+        key: K,
+        expires_at: Option<u64>,
+    },
+    Cleared,
+    Committed {
+        changes: Vec<(K, Option<Logged<V>>)>,
+    },
+    /// The whole keyspace, as in a snapshot, replacing everything before it.
+    Image {
+        entries: Vec<Saved<K, V>>,
+    },
+}
+
+/// Appends a frame holding the payload written by `encode` to `out`.
+fn write_frame(out: &mut Vec<u8>, encode: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.resize(start + FRAME, 0);
+    encode(out);
+    let payload = start + FRAME;
+    let len = (out.len() - payload) as u32;
+    let checksum = crc32(&out[payload..]);
+    out[start..start + 4].copy_from_slice(&len.to_le_bytes());
+    out[start + 4..payload].copy_from_slice(&checksum.to_le_bytes());
+}
+
+fn encode_event<K: Persist, V: Persist>(event: &ChangeEvent<K, V>, out: &mut Vec<u8>) {
+    event.revision().encode(out);
+    match event {
+        ChangeEvent::Set {
+            key,
+            new,
+            expires_at,
+            ..
+        } => {
+            out.push(0);
+            key.encode(out);
+            new.encode(out);
+            expires_at.map(|at| Clock::now().wall_clock(at)).encode(out);
+        }
+        ChangeEvent::Removed { key, .. } | ChangeEvent::Expired { key, .. } => {
+            out.push(1);
+            key.encode(out);
+        }
+        ChangeEvent::Edited { key, edit, .. } => {
+            out.push(2);
+            key.encode(out);
+            edit.encode(out);
+        }
+        ChangeEvent::TtlChanged {
+            key, expires_at, ..
+        } => {
+            out.push(3);
+            key.encode(out);
+            expires_at.map(|at| Clock::now().wall_clock(at)).encode(out);
+        }
+        ChangeEvent::Cleared { .. } => out.push(4),
+        ChangeEvent::Committed { changes, .. } => {
+            out.push(5);
+            changes.len().encode(out);
+            for change in changes {
+                change.key().encode(out);
+                match change {
+                    Change::Set {
+                        new, expires_at, ..
+                    } => {
+                        out.push(1);
+                        new.encode(out);
+                        expires_at.map(|at| Clock::now().wall_clock(at)).encode(out);
+                    }
+                    // Matches the encoding of `None` for the decoded `Option`
+                    Change::Removed { .. } => out.push(0),
+                }
+            }
+        }
+    }
+}
+
+fn decode_record<K: Persist, V: Persist>(
+    mut input: &[u8],
+) -> Result<(u64, Record<K, V>), DecodeError> {
+    let input = &mut input;
+    let revision = u64::decode(input)?;
+    let record = match u8::decode(input)? {
+        0 => Record::Set {
+            key: K::decode(input)?,
+            value: V::decode(input)?,
+            expires_at: Persist::decode(input)?,
+        },
+        1 => Record::Removed {
+            key: K::decode(input)?,
+        },
+        2 => Record::Edited {
+            key: K::decode(input)?,
+            edit: Edit::decode(input)?,
+        },
+        3 => Record::TtlChanged {
+            key: K::decode(input)?,
+            expires_at: Persist::decode(input)?,
+        },
+        4 => Record::Cleared,
+        5 => Record::Committed {
+            changes: Persist::decode(input)?,
+        },
+        6 => Record::Image {
+            entries: Persist::decode(input)?,
+        },
+        tag => return Err(DecodeError::UnknownTag { tag }),
+    };
+    Ok((revision, record))
+}
+
+/// Replays records onto the keyspace they were logged from.
+struct Replay<K, V> {
+    data: HashMap<K, Entry<V>>,
+    revision: u64,
+    /// When the replay started.
+    clock: Clock,
+}
+
+impl<K: StoreKey, V: StoreData> Replay<K, V> {
+    fn apply(&mut self, revision: u64, record: Record<K, V>) {
+        self.revision = revision;
+        match record {
+            Record::Set {
+                key,
+                value,
+                expires_at,
+            } => self.set(key, value, expires_at),
+            Record::Removed { key } => {
+                self.data.remove(&key);
+            }
+            Record::Edited { key, edit } => {
+                let entry = match self.data.entry(key) {
+                    hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                    hash_map::Entry::Vacant(entry) => match V::created_by(&edit) {
+                        Some(value) => entry.insert(Entry {
+                            value,
+                            expires_at: None,
+                            revision,
+                        }),
+                        None => return,
+                    },
+                };
+                entry.value.apply_edit(&edit);
+                entry.revision = revision;
+            }
+            Record::TtlChanged { key, expires_at } => {
+                let deadline = expires_at.map(|at| self.clock.instant(at));
+                if let Some(entry) = self.data.get_mut(&key) {
+                    entry.expires_at = deadline;
+                }
+            }
+            Record::Cleared => self.data.clear(),
+            Record::Committed { changes } => {
+                for (key, change) in changes {
+                    match change {
+                        Some((value, expires_at)) => self.set(key, value, expires_at),
+                        None => {
+                            self.data.remove(&key);
+                        }
+                    }
+                }
+            }
+            Record::Image { entries } => self.data = snapshot::load(entries, self.clock),
+        }
+    }
+
+    fn set(&mut self, key: K, value: V, expires_at: Option<u64>) {
+        let entry = Entry {
+            value,
+            expires_at: expires_at.map(|at| self.clock.instant(at)),
+            revision: self.revision,
+        };
+        self.data.insert(key, entry);
+    }
+}
+
+/**
+ * One moment on both the monotonic and the wall clock. Deadlines are
+ * [`Instant`]s in memory but wall clock milliseconds on disk, so that they
+ * survive a restart.
+ */
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Clock {
+    wall: u64,
+    now: Instant,
+}
+
+impl Clock {
+    pub(crate) fn now() -> Self {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        Clock {
+            wall,
+            now: Instant::now(),
+        }
+    }
+
+    /// Wall clock milliseconds of `deadline`.
+    pub(crate) fn wall_clock(&self, deadline: Instant) -> u64 {
+        match deadline.checked_duration_since(self.now) {
+            Some(ahead) => self.wall + ahead.as_millis() as u64,
+            None => self
+                .wall
+                .saturating_sub(self.now.duration_since(deadline).as_millis() as u64),
+        }
+    }
+
+    /// The instant of a saved deadline, which may already have passed.
+    pub(crate) fn instant(&self, at: u64) -> Instant {
+        if at >= self.wall {
+            self.now + Duration::from_millis(at - self.wall)
+        } else {
+            self.now
+                .checked_sub(Duration::from_millis(self.wall - at))
+                .unwrap_or(self.now)
+        }
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// Lookup table of the reflected CRC-32 polynomial used by zlib and Ethernet.
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+impl<K, V> ReactiveStore<K, V>
+where
+    K: StoreKey + Persist,
+    V: StoreData + Persist,
+{
+    /**
+     * Opens a store that appends every change to the log at `log.path`,
+     * creating the log if needed. An existing log is replayed first, which
+     * restores every key with its remaining TTL, keys whose deadline passed
+     * meanwhile expiring as usual. Revisions continue where the log left
+     * off.
+     *
+     * Bytes at the end of the log that do not form a valid record are cut
+     * off and reported in the returned [`Recovery`]. A damaged record with
+     * intact ones after it is not what a crash leaves behind, so it fails
+     * with [`LogError::Corrupt`] and the log is left untouched. Unless
+     * `log.compaction` is `None`, a background thread rewrites the log
+     * whenever it grows past the trigger.
+     *
+     * Once appending to the log or syncing it fails, every write is refused
+     * with [`StoreError::LogFailed`](crate::reactive_store::StoreError::LogFailed)
+     * until [`ReactiveStore::compact`] manages to rewrite the log. The
+     * write that hit the failure returns the error as well, and is undone.
+     * To be able to undo it, a write copies what it changes first.
+     */
+    pub fn open(config: StoreConfig, log: LogConfig) -> Result<(Self, Recovery), LogError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&log.path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        if bytes.len() < MAGIC.len() {
+            if !MAGIC.starts_with(&bytes) {
+                return Err(LogError::NotALog { path: log.path });
+            }
+            // New, or the header itself was torn
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(MAGIC)?;
+            file.sync_all()?;
+            bytes = MAGIC.to_vec();
+        } else if !bytes.starts_with(MAGIC) {
+            return Err(LogError::NotALog { path: log.path });
+        }
+
+        let mut replay: Replay<K, V> = Replay {
+            data: HashMap::new(),
+            revision: 0,
+            clock: Clock::now(),
+        };
+        let mut offset = MAGIC.len();
+        let mut records = 0;
+        while let Some(payload) = frame(&bytes[offset..]) {
+            let (revision, record) =
+                decode_record(payload).map_err(|source| LogError::Malformed {
+                    offset: offset as u64,
+                    source,
+                })?;
+            replay.apply(revision, record);
+            offset += FRAME + payload.len();
+            records += 1;
+        }
+
+        let rest = &bytes[offset..];
+        if (1..rest.len()).any(|start| frame(&rest[start..]).is_some()) {
+            return Err(LogError::Corrupt {
+                offset: offset as u64,
+            });
+        }
+        let truncated = rest.len() as u64;
+        if truncated > 0 {
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::End(0))?;
+
+        let Replay { data, revision, .. } = replay;
+        let background = log.compaction.is_some();
+        let (requests, compactions) = mpsc::sync_channel(1);
+        let wal: Arc<dyn EventLog<K, V>> = Wal::new(file, offset as u64, log, requests)?;
+        let store = Self::with_parts(config, data, revision, Some(wal));
+        if background {
+            let weak = store.downgrade();
+            thread::Builder::new()
+                .name("r-reactive-compact".into())
+                .spawn(move || compact_on_request(weak, compactions))?;
+        }
+        Ok((store, Recovery { records, truncated }))
+    }
+}
+
+/**
+ * The payload of the first record in `bytes`, if it is complete and intact.
+ * Every record has a payload, which keeps a run of zeroes, as a crash can
+ * leave at the end of a file, from passing for records.
+ */
+fn frame(bytes: &[u8]) -> Option<&[u8]> {
+    let header = bytes.get(..FRAME)?;
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let payload = bytes.get(FRAME..FRAME.checked_add(len)?)?;
+    (len > 0 && crc32(payload) == checksum).then_some(payload)
+}
+
+impl<K: StoreKey, V: StoreData> ReactiveStore<K, V> {
+    /**
+     * Forces every logged change to disk, whatever the [`FsyncPolicy`].
+     * Fails if this or an earlier write to the log failed, after which the
+     * store refuses writes. Does nothing for a store without a log.
+     */
+    pub fn sync_log(&self) -> Result<(), LogError> {
+        match self.events.log() {
+            Some(log) => log.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /**
+     * Takes the error of the last compaction the background thread ran, if
+     * it failed and no compaction has succeeded since. A failed compaction
+     * leaves the old log in use, and the thread retries on the next append
+     * past the trigger.
+     */
+    pub fn take_compaction_error(&self) -> Option<LogError> {
+        self.events
+            .log()
+            .and_then(|log| log.take_compaction_error())
+    }
+
+    /**
+     * Rewrites the log as an image of the current keyspace, followed by
+     * the changes made while it was written, and atomically renames it
+     * over the old log. Writers only wait while the keyspace is copied and
+     * while the last changes are copied before the rename. If anything
+     * fails the old log stays in use. A successful rewrite also ends the
+     * refusal of writes after a log failure. Does nothing for a store
+     * without a log.
+     */
+    pub fn compact(&self) -> Result<(), LogError> {
+        let Some(log) = self.events.log() else {
+            return Ok(());
+        };
+        let _rewriting = log.rewriting();
+        self.compact_locked(log)
+    }
+
+    /// Rewrites the log, with [`EventLog::rewriting`] held by the caller.
+    fn compact_locked(&self, log: &dyn EventLog<K, V>) -> Result<(), LogError> {
+        let (revision, entries) = {
+            // Nothing is published under the read lock, so the records
+            // copied aside start right after the image
+            let data = self.data.read().unwrap();
+            log.begin_rewrite();
+            self.image_locked(&data)
+        };
+        log.finish_rewrite(revision, entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive_store::{SortedSet, StoreError, StoreValue, Ttl};
+    use std::fs;
+    use std::path::Path;
+
+    fn log_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("r-reactive-{}-{name}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn open(path: &Path) -> (ReactiveStore, Recovery) {
+        let log = LogConfig {
+            path: path.to_path_buf(),
+            fsync: FsyncPolicy::Always,
+            compaction: None,
+        };
+        ReactiveStore::open(StoreConfig::default(), log).unwrap()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_replay_restores_every_change() {
+        let path = log_path("replay");
+        let (store, recovery) = open(&path);
+        assert_eq!(
+            recovery,
+            Recovery {
+                records: 0,
+                truncated: 0
+            }
+        );
+
+        store.set("text", "hello".into()).unwrap();
+        store
+            .set_with_ttl("session", "token".into(), Duration::from_secs(60))
+            .unwrap();
+        store
+            .set_with_ttl("gone", "soon".into(), Duration::from_millis(1))
+            .unwrap();
+        store.incr_by("visits", 3).unwrap();
+        store.push_back("queue", 1.into()).unwrap();
+        store.push_back("queue", 2.into()).unwrap();
+        store.pop_front("queue").unwrap();
+        store.sadd("tags", &["a", "b"]).unwrap();
+        store.zadd("scores", &[("bob", 1.5)]).unwrap();
+        store.set_path("user", "name", "bob".into()).unwrap();
+        store
+            .set_many([("x".to_string(), 1.into()), ("y".to_string(), 2.into())])
+            .unwrap();
+        store.remove("x").unwrap();
+        store.persist("text").unwrap();
+        let snapshot: HashMap<String, StoreValue> = [
+            "text", "session", "visits", "queue", "tags", "scores", "user", "y",
+        ]
+        .into_iter()
+        .map(|key| (key.to_string(), store.get(key).unwrap()))
+        .collect();
+        let revision = store.revision();
+        let versioned = store.get_versioned("visits");
+        drop(store);
+
+        std::thread::sleep(Duration::from_millis(5));
+        let (store, recovery) = open(&path);
+        assert_eq!(recovery.truncated, 0);
+        assert_eq!(recovery.records, revision);
+        for (key, value) in &snapshot {
+            assert_eq!(store.get(key).as_ref(), Some(value), "{key}");
+        }
+        assert_eq!(store.get("queue"), Some(StoreValue::List(vec![2.into()])));
+        assert!(store.get("x").is_none());
+        assert!(store.get("gone").is_none());
+        assert!(matches!(
+            store.ttl("session"),
+            Some(Ttl::Remaining(left)) if left > Duration::from_secs(55)
+        ));
+        assert_eq!(store.get_versioned("visits"), versioned);
+        let mut scores = SortedSet::new();
+        scores.insert("bob", 1.5);
+        assert_eq!(store.get("scores"), Some(StoreValue::SortedSet(scores)));
+
+        // New changes continue the revisions and the log
+        assert!(store.revision() > revision);
+        store.incr_by("visits", 1).unwrap();
+        drop(store);
+        let (store, _) = open(&path);
+        assert_eq!(store.get("visits"), Some(StoreValue::Counter(4)));
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_tail_is_truncated() {
+        let path = log_path("tail");
+        let (store, _) = open(&path);
+        store.set("a", 1.into()).unwrap();
+        store.set("b", 2.into()).unwrap();
+        drop(store);
+        let valid = fs::metadata(&path).unwrap().len();
+
+        // A torn record: the frame promises more bytes than follow
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[200, 0, 0, 0, 1, 2, 3, 4, 5]).unwrap();
+        drop(file);
+
+        let (store, recovery) = open(&path);
+        assert_eq!(
+            recovery,
+            Recovery {
+                records: 2,
+                truncated: 9
+            }
+        );
+        assert_eq!(fs::metadata(&path).unwrap().len(), valid);
+        assert_eq!(store.get("b"), Some(2.into()));
+        store.set("c", 3.into()).unwrap();
+        drop(store);
+
+        // A flipped bit in the last record drops that record only
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let (store, recovery) = open(&path);
+        assert_eq!(recovery.records, 2);
+        assert!(store.get("c").is_none());
+        drop(store);
+
+        // Damage before intact records is refused, and nothing is cut
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[MAGIC.len() + FRAME] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let log = LogConfig::new(&path);
+        assert!(matches!(
+            ReactiveStore::<String, StoreValue>::open(StoreConfig::default(), log),
+            Err(LogError::Corrupt { offset }) if offset == MAGIC.len() as u64
+        ));
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+
+        fs::write(&path, b"not a log at all").unwrap();
+        let log = LogConfig::new(&path);
+        assert!(matches!(
+            ReactiveStore::<String, StoreValue>::open(StoreConfig::default(), log),
+            Err(LogError::NotALog { .. })
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_log_failure_refuses_writes() {
+        let path = log_path("failure");
+        fs::write(&path, MAGIC).unwrap();
+        // Appends to a file opened for reading fail
+        let file = File::open(&path).unwrap();
+        let config = LogConfig {
+            path: path.clone(),
+            fsync: FsyncPolicy::Never,
+            compaction: None,
+        };
+        let (requests, _) = mpsc::sync_channel(1);
+        let wal = Wal::new(file, MAGIC.len() as u64, config, requests).unwrap();
+        let store = ReactiveStore::with_parts(StoreConfig::default(), HashMap::new(), 0, Some(wal));
+
+        assert!(matches!(
+            store.set("a", 1.into()),
+            Err(StoreError::LogFailed { .. })
+        ));
+        assert!(matches!(
+            store.set("b", 2.into()),
+            Err(StoreError::LogFailed { .. })
+        ));
+        assert!(matches!(
+            store.push_back("list", 1.into()),
+            Err(StoreError::LogFailed { .. })
+        ));
+        assert!(store.sync_log().is_err());
+        assert!(store.get("a").is_none());
+        assert!(store.get("b").is_none());
+        assert_eq!(store.revision(), 0);
+
+        // Rewriting the log from the store ends the refusal
+        store.compact().unwrap();
+        store.set("a", 1.into()).unwrap();
+        store.set("b", 2.into()).unwrap();
+        store.sync_log().unwrap();
+        drop(store);
+        let (store, _) = open(&path);
+        assert_eq!(store.get("a"), Some(1.into()));
+        assert_eq!(store.get("b"), Some(2.into()));
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_compact_rewrites_the_log() {
+        let path = log_path("compact");
+        let (store, _) = open(&path);
+        for _ in 0..1000 {
+            store.incr_by("hits", 1).unwrap();
+        }
+        store
+            .set_with_ttl("session", "token".into(), Duration::from_secs(60))
+            .unwrap();
+        let before = fs::metadata(&path).unwrap().len();
+        store.compact().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() < before / 10);
+
+        // Writes racing a rewrite land in the new log
+        let writer = {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    store
+                        .set(format!("key{i}").as_str(), StoreValue::Counter(i))
+                        .unwrap();
+                }
+            })
+        };
+        for _ in 0..5 {
+            store.compact().unwrap();
+        }
+        writer.join().unwrap();
+        let revision = store.revision();
+        drop(store);
+
+        let (store, _) = open(&path);
+        assert_eq!(store.get("hits"), Some(StoreValue::Counter(1000)));
+        for i in 0..1000 {
+            let key = format!("key{i}");
+            assert_eq!(store.get(&key), Some(StoreValue::Counter(i)), "{key}");
+        }
+        assert!(matches!(store.ttl("session"), Some(Ttl::Remaining(_))));
+        assert_eq!(store.revision(), revision);
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_growth_triggers_compaction() {
+        let increments = |path: &Path, compaction| {
+            let log = LogConfig {
+                path: path.to_path_buf(),
+                fsync: FsyncPolicy::Never,
+                compaction,
+            };
+            let (store, _) =
+                ReactiveStore::<String, StoreValue>::open(StoreConfig::default(), log).unwrap();
+            for _ in 0..20_000 {
+                store.incr_by("hits", 1).unwrap();
+            }
+            store
+        };
+        let full = log_path("full");
+        drop(increments(&full, None));
+        let uncompacted = fs::metadata(&full).unwrap().len();
+        fs::remove_file(&full).unwrap();
+
+        let path = log_path("growth");
+        let trigger = CompactionTrigger {
+            growth: 2.0,
+            min_size: 16 << 10,
+        };
+        let store = increments(&path, Some(trigger));
+        // The last rewrite may still be running
+        let compacted = (0..100).any(|_| {
+            std::thread::sleep(Duration::from_millis(20));
+            fs::metadata(&path).unwrap().len() < uncompacted / 4
+        });
+        assert!(compacted);
+        drop(store);
+
+        let (store, _) = open(&path);
+        assert_eq!(store.get("hits"), Some(StoreValue::Counter(20_000)));
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_background_compaction_is_kept() {
+        let path = log_path("blocked");
+        // The rewrite cannot create its file where a directory is in the way
+        let mut partial = path.clone().into_os_string();
+        partial.push(".rewrite");
+        fs::create_dir_all(&partial).unwrap();
+        let log = LogConfig {
+            path: path.clone(),
+            fsync: FsyncPolicy::Never,
+            compaction: Some(CompactionTrigger {
+                growth: 2.0,
+                min_size: 1 << 10,
+            }),
+        };
+        let (store, _) =
+            ReactiveStore::<String, StoreValue>::open(StoreConfig::default(), log).unwrap();
+        for _ in 0..1000 {
+            store.incr_by("hits", 1).unwrap();
+        }
+        let failed = (0..100).find_map(|_| {
+            std::thread::sleep(Duration::from_millis(20));
+            store.take_compaction_error()
+        });
+        assert!(matches!(failed, Some(LogError::Io(_))));
+
+        // The old log stayed in use, and a later compaction clears the error
+        fs::remove_dir(&partial).unwrap();
+        store.compact().unwrap();
+        assert!(store.take_compaction_error().is_none());
+        assert_eq!(store.get("hits"), Some(StoreValue::Counter(1000)));
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_log_starting_with_an_image() {
+        let path = log_path("image");
+        let source = ReactiveStore::new();
+        source.set("a", 1.into()).unwrap();
+        source
+            .set_with_ttl("b", 2.into(), Duration::from_secs(60))
+            .unwrap();
+        let (revision, entries) = source.image();
+
+        let mut bytes = MAGIC.to_vec();
+        write_frame(&mut bytes, |out| {
+            revision.encode(out);
+            out.push(6);
+            entries.encode(out);
+        });
+        let event: ChangeEvent = ChangeEvent::Removed {
+            revision: revision + 1,
+            key: "a".into(),
+            old: 1.into(),
+        };
+        write_frame(&mut bytes, |out| encode_event(&event, out));
+        fs::write(&path, &bytes).unwrap();
+
+        let (store, recovery) = open(&path);
+        assert_eq!(recovery.records, 2);
+        assert!(store.get("a").is_none());
+        assert_eq!(store.get_versioned("b"), source.get_versioned("b"));
+        assert!(matches!(store.ttl("b"), Some(Ttl::Remaining(_))));
+        assert_eq!(store.revision(), revision + 1);
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+}